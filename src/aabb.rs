@@ -0,0 +1,77 @@
+use std::f32;
+
+use vec3::*;
+use ray::*;
+
+// Axis aligned bounding box
+//
+#[derive(Copy, Clone, Debug)]
+pub struct Aabb {
+    min: Vec3,
+    max: Vec3
+}
+
+impl Aabb {
+    pub fn new(a: Vec3, b: Vec3) -> Aabb { Aabb { min:minimum(a, b), max:maximum(a, b) } }
+
+    // A box containing nothing - the identity for surrounding_box()
+    pub fn empty() -> Aabb {
+        Aabb { min:Vec3::new(f32::MAX, f32::MAX, f32::MAX), max:Vec3::new(-f32::MAX, -f32::MAX, -f32::MAX) }
+    }
+
+    pub fn min(&self) -> Vec3 { self.min }
+    pub fn max(&self) -> Vec3 { self.max }
+    pub fn centroid(&self) -> Vec3 { 0.5 * (self.min + self.max) }
+    pub fn is_empty(&self) -> bool { self.min.x() > self.max.x() || self.min.y() > self.max.y() || self.min.z() > self.max.z() }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    // Index of the axis along which the box is largest
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x() > d.y() && d.x() > d.z() {
+            0
+        } else if d.y() > d.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Slab test - does the ray pass through the box anywhere in [t_min, t_max]?
+    pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.range(r, t_min, t_max).is_some()
+    }
+
+    // Where the ray is inside the box, within [t_min, t_max]
+    pub fn range(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+
+        for a in 0..3 {
+            let inv_d = 1.0 / r.direction()[a];
+            let mut ta = (self.min[a] - r.origin()[a]) * inv_d;
+            let mut tb = (self.max[a] - r.origin()[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut ta, &mut tb);
+            }
+            t0 = t0.max(ta);
+            t1 = t1.min(tb);
+            if t1 < t0 {
+                return None;
+            }
+        }
+
+        Some((t0, t1))
+    }
+}
+
+pub fn surrounding_box(b0: Aabb, b1: Aabb) -> Aabb {
+    Aabb { min:minimum(b0.min, b1.min), max:maximum(b0.max, b1.max) }
+}
@@ -0,0 +1,163 @@
+use std::f32::consts::PI;
+use std::path::Path;
+
+use image;
+
+use vec3::*;
+use texture::*;
+
+// What rays that leave the scene see
+//
+// Backgrounds that vary a lot with direction can also be sampled like a light source, so that
+// surfaces pick up light from their bright parts directly rather than by chance.
+//
+pub trait Background: Send + Sync {
+    // Light arriving from direction d (pointing away from the scene)
+    fn value(&self, d: Vec3) -> Vec3;
+
+    //   random() - a direction to sample light from
+    //   pdf_value() - solid angle density with which random() would pick direction d
+    fn is_sampled(&self) -> bool { false }
+    fn pdf_value(&self, _d: Vec3) -> f32 { 0.0 }
+    fn random(&self) -> Vec3 { Vec3::new(0.0, 1.0, 0.0) }
+}
+
+// The book's blue-white gradient
+//
+pub struct Gradient;
+
+impl Background for Gradient {
+    fn value(&self, d: Vec3) -> Vec3 {
+        let unit_direction = unit_vector(d);
+        let t = 0.5 * (unit_direction.y() + 1.0);
+        (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+    }
+}
+
+// The same colour in every direction
+//
+pub struct Constant {
+    colour: Vec3
+}
+
+impl Constant {
+    pub fn new(c: Vec3) -> Constant { Constant { colour:c } }
+}
+
+impl Background for Constant {
+    fn value(&self, _d: Vec3) -> Vec3 {
+        self.colour
+    }
+}
+
+// Piecewise constant distribution over 0..n, sampled by inverting its CDF
+//
+struct Distribution {
+    weights: Vec<f32>,
+    cdf: Vec<f32>,      // Running total of weights
+    total: f32
+}
+
+impl Distribution {
+    fn new(weights: Vec<f32>) -> Distribution {
+        let mut total = 0.0;
+        let cdf = weights.iter().map(|w| { total += w; total }).collect();
+        Distribution { weights, cdf, total }
+    }
+
+    fn sample(&self) -> usize {
+        let a = rand() * self.total;
+        self.cdf.partition_point(|&c| c <= a).min(self.cdf.len() - 1)
+    }
+
+    fn probability(&self, i: usize) -> f32 {
+        if self.total > 0.0 { self.weights[i] / self.total } else { 0.0 }
+    }
+}
+
+fn luminance(c: Vec3) -> f32 {
+    0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+}
+
+// Equirectangular (latitude-longitude) environment map
+//
+// The top row of the image is straight up, and the middle column looks along -z, turned about y
+// by the rotation. Directions are sampled in proportion to the brightness of each pixel times
+// the solid angle it covers - first a row from the marginal distribution, then a pixel within it.
+//
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,      // Top row first
+    scale: f32,
+    rotation: f32,          // Radians about y
+    rows: Distribution,
+    columns: Vec<Distribution>
+}
+
+impl EnvironmentMap {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>, scale: f32, rotation_degrees: f32) -> EnvironmentMap {
+        assert!(width > 0 && height > 0, "empty environment map");
+        assert_eq!(pixels.len(), width * height, "environment map size mismatch");
+
+        let columns: Vec<Distribution> = (0..height).map(|j| {
+            let sin_theta = (PI * (j as f32 + 0.5) / height as f32).sin();
+            Distribution::new(pixels[j * width..(j + 1) * width].iter().map(|&c| luminance(c).max(0.0) * sin_theta).collect())
+        }).collect();
+        let rows = Distribution::new(columns.iter().map(|c| c.total).collect());
+
+        EnvironmentMap { width, height, pixels, scale, rotation: rotation_degrees * PI / 180.0, rows, columns }
+    }
+
+    pub fn load(path: &Path, scale: f32, rotation_degrees: f32) -> image::ImageResult<EnvironmentMap> {
+        let (width, height, pixels) = load_linear(path)?;
+        Ok(EnvironmentMap::new(width, height, pixels, scale, rotation_degrees))
+    }
+
+    // Image coordinates in [0, 1] of direction d
+    fn to_image(&self, d: Vec3) -> (f32, f32) {
+        let d = unit_vector(d);
+        let theta = d.y().clamp(-1.0, 1.0).acos();
+        let phi = d.x().atan2(-d.z()) - self.rotation;
+        let u = (0.5 + phi / (2.0 * PI)).rem_euclid(1.0);
+        (u, theta / PI)
+    }
+
+    fn pixel(&self, x: f32, y: f32) -> (usize, usize) {
+        (((x * self.width as f32) as usize).min(self.width - 1), ((y * self.height as f32) as usize).min(self.height - 1))
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn value(&self, d: Vec3) -> Vec3 {
+        let (x, y) = self.to_image(d);
+        let (i, j) = self.pixel(x, y);
+        self.scale * self.pixels[j * self.width + i]
+    }
+
+    fn is_sampled(&self) -> bool { self.rows.total > 0.0 }
+
+    fn pdf_value(&self, d: Vec3) -> f32 {
+        let (x, y) = self.to_image(d);
+        let sin_theta = (PI * y).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let (i, j) = self.pixel(x, y);
+        let p = self.rows.probability(j) * self.columns[j].probability(i);
+
+        // Each pixel covers (2 pi / width) * (pi / height) * sin theta of solid angle
+        p * (self.width * self.height) as f32 / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn random(&self) -> Vec3 {
+        let j = self.rows.sample();
+        let i = self.columns[j].sample();
+        let x = (i as f32 + rand()) / self.width as f32;
+        let y = (j as f32 + rand()) / self.height as f32;
+
+        let theta = PI * y;
+        let phi = 2.0 * PI * (x - 0.5) + self.rotation;
+        Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+}
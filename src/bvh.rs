@@ -0,0 +1,145 @@
+use ray::*;
+use material::*;
+use hitable::*;
+use aabb::*;
+
+// Bounding volume hierarchy, built using a binned surface area heuristic
+//
+
+// Number of buckets centroids are sorted into when looking for a split
+const SAH_BINS: usize = 16;
+
+// Cost of visiting a node, relative to testing one object
+const TRAVERSAL_COST: f32 = 0.5;
+
+// Leaves are never bigger than this, even if the SAH would prefer it
+const MAX_LEAF_SIZE: usize = 8;
+
+struct BvhItem {
+    bbox: Aabb,
+    object: Box<dyn Hitable>
+}
+
+enum BvhContents {
+    Leaf(HitableList),
+    Split { axis: usize, left: Box<BvhNode>, right: Box<BvhNode> }
+}
+
+pub struct BvhNode {
+    bbox: Aabb,
+    contents: BvhContents
+}
+
+impl BvhNode {
+    // All objects must be bounded
+    pub fn new(l: Vec<Box<dyn Hitable>>) -> BvhNode {
+        let items = l.into_iter().map(|object| {
+            let bbox = object.axis_aligned_bounding_box().expect("unbounded object in BVH");
+            BvhItem { bbox, object }
+        }).collect();
+
+        BvhNode::build(items)
+    }
+
+    fn leaf(bbox: Aabb, items: Vec<BvhItem>) -> BvhNode {
+        BvhNode { bbox, contents: BvhContents::Leaf(HitableList::new(items.into_iter().map(|i| i.object).collect())) }
+    }
+
+    fn build(items: Vec<BvhItem>) -> BvhNode {
+        let bbox = items.iter().fold(Aabb::empty(), |b, i| surrounding_box(b, i.bbox));
+        let centroids = items.iter().fold(Aabb::empty(), |b, i| surrounding_box(b, Aabb::new(i.bbox.centroid(), i.bbox.centroid())));
+        let n = items.len();
+
+        if n <= 1 {
+            return BvhNode::leaf(bbox, items);
+        }
+
+        let bin_of = |item: &BvhItem, axis: usize| {
+            let extent = centroids.max()[axis] - centroids.min()[axis];
+            let b = ((item.bbox.centroid()[axis] - centroids.min()[axis]) / extent * SAH_BINS as f32) as usize;
+            b.min(SAH_BINS - 1)
+        };
+
+        // Find cheapest split over all axes - (cost, axis, first bin on right)
+        let mut best: Option<(f32, usize, usize)> = None;
+
+        for axis in 0..3 {
+            if centroids.max()[axis] <= centroids.min()[axis] {
+                continue;
+            }
+
+            let mut bins = [(Aabb::empty(), 0usize); SAH_BINS];
+            for i in &items {
+                let b = bin_of(i, axis);
+                bins[b].0 = surrounding_box(bins[b].0, i.bbox);
+                bins[b].1 += 1;
+            }
+
+            // Sweep from the right to get area*count of every suffix
+            let mut right_cost = [0.0f32; SAH_BINS];
+            let mut acc = (Aabb::empty(), 0usize);
+            for b in (1..SAH_BINS).rev() {
+                acc = (surrounding_box(acc.0, bins[b].0), acc.1 + bins[b].1);
+                right_cost[b] = acc.0.surface_area() * acc.1 as f32;
+            }
+
+            // Then from the left, combining with the suffixes
+            let mut acc = (Aabb::empty(), 0usize);
+            for b in 1..SAH_BINS {
+                acc = (surrounding_box(acc.0, bins[b-1].0), acc.1 + bins[b-1].1);
+                if acc.1 == 0 || acc.1 == n {
+                    continue;
+                }
+                let cost = TRAVERSAL_COST + (acc.0.surface_area() * acc.1 as f32 + right_cost[b]) / bbox.surface_area();
+                if best.is_none_or(|(c, _, _)| cost < c) {
+                    best = Some((cost, axis, b));
+                }
+            }
+        }
+
+        let (left, right, axis) = match best {
+            Some((cost, axis, split)) => {
+                if n <= MAX_LEAF_SIZE && n as f32 <= cost {
+                    return BvhNode::leaf(bbox, items);
+                }
+                let (l, r): (Vec<BvhItem>, Vec<BvhItem>) = items.into_iter().partition(|i| bin_of(i, axis) < split);
+                (l, r, axis)
+            }
+            None => {
+                // All centroids coincide - nothing to choose between, so split by count
+                if n <= MAX_LEAF_SIZE {
+                    return BvhNode::leaf(bbox, items);
+                }
+                let mut l = items;
+                let r = l.split_off(n / 2);
+                (l, r, 0)
+            }
+        };
+
+        BvhNode { bbox, contents: BvhContents::Split { axis, left: Box::new(BvhNode::build(left)), right: Box::new(BvhNode::build(right)) } }
+    }
+}
+
+impl Hitable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return None;
+        }
+
+        match self.contents {
+            BvhContents::Leaf(ref list) => list.hit(r, t_min, t_max),
+            BvhContents::Split { axis, ref left, ref right } => {
+                // Visit the nearer child first, so the far one can be culled against its hit
+                let (first, second) = if r.direction()[axis] < 0.0 { (right, left) } else { (left, right) };
+                match first.hit(r, t_min, t_max) {
+                    Some(rec) => second.hit(r, t_min, rec.t).or(Some(rec)),
+                    None => second.hit(r, t_min, t_max)
+                }
+            }
+        }
+    }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
@@ -14,7 +14,10 @@ pub struct Camera {
     v:Vec3,
     _w:Vec3,
 
-    lens_radius:f32
+    lens_radius:f32,
+    half_height:f32,
+
+    shutter:(f32, f32)      // Times the shutter opens and closes
 }
 
 impl Camera {
@@ -32,14 +35,25 @@ impl Camera {
             lower_left_corner: lookfrom - focus * (half_width*u + half_height*v + w),
             horizontal: focus * (2.0 * half_width * u),
             vertical: focus * (2.0 * half_height * v),
-            u: u, v: v, _w: w,
-            lens_radius: aperture/2.0
+            u, v, _w: w,
+            lens_radius: aperture/2.0,
+            half_height,
+            shutter: (0.0, 0.0)
         }
     }
 
+    // Moving objects are only bounded from time 0 to 1, so the shutter must stay inside that
+    pub fn with_shutter(self, open: f32, close: f32) -> Camera { Camera { shutter:(open, close), ..self } }
+
+    // Angle covered by one pixel, in an image the given number of pixels high
+    pub fn pixel_spread(&self, ny: usize) -> f32 {
+        2.0 * self.half_height / ny as f32
+    }
+
     pub fn get_ray(&self, u: f32, v:f32) -> Ray {
         let rd = self.lens_radius * random_in_unit_disk();
         let offset = self.u * rd.x() + self.v * rd.y();
-        Ray::new(self.origin + offset, self.lower_left_corner + u*self.horizontal + v*self.vertical - self.origin - offset) }
+        let time = self.shutter.0 + rand() * (self.shutter.1 - self.shutter.0);
+        Ray::new(self.origin + offset, self.lower_left_corner + u*self.horizontal + v*self.vertical - self.origin - offset).with_time(time) }
 }
 
@@ -0,0 +1,33 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+// Failure reading one of the input files - either it couldn't be read at all, or a line was bad
+//
+#[derive(Debug)]
+pub enum LoadError {
+    Io { path: String, error: io::Error },
+    Syntax { path: String, line: usize, message: String }
+}
+
+impl LoadError {
+    pub fn io(path: &Path, error: io::Error) -> LoadError {
+        LoadError::Io { path: path.display().to_string(), error }
+    }
+
+    pub fn syntax(path: &Path, line: usize, message: String) -> LoadError {
+        LoadError::Syntax { path: path.display().to_string(), line, message }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io { ref path, ref error } => write!(f, "{}: {}", path, error),
+            LoadError::Syntax { ref path, line, ref message } => write!(f, "{}:{}: {}", path, line, message)
+        }
+    }
+}
+
+impl Error for LoadError {}
@@ -0,0 +1,104 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use vec3::*;
+use material::*;
+use texture::*;
+use spectrum::*;
+
+// Thin film interference - a clear coating a few hundred nanometres thick, like a soap bubble,
+// oil on water or the oxide on anodised metal. Light reflected off the top and bottom of the
+// film interferes, so the reflection is brighter at some wavelengths and darker at others,
+// with colours that shift with the angle and the thickness.
+//
+// The reflectance is the sum of every bounce inside the film (Airy's formula), for each
+// polarisation. A metal underneath is taken to reflect with a half wave phase shift, as a
+// perfect conductor would.
+//
+// Thickness is in nanometres, optionally scaled by a texture (the average of its channels), so
+// that it can swirl like a bubble's or run like an oil slick's.
+//
+pub struct ThinFilm {
+    thickness: f32,
+    ior: f32,
+    variation: Option<Arc<dyn Texture>>
+}
+
+// What is under the film
+#[derive(Copy, Clone)]
+pub enum Substrate {
+    Dielectric(f32),        // Index of refraction
+    Metal(f32)              // Reflectance
+}
+
+// Reflectance of one polarisation, from the amplitudes reflected at the top and bottom of the
+// film and the phase difference between them
+fn airy(r12: f32, r23: f32, phase: f32) -> f32 {
+    let c = 2.0 * r12 * r23 * phase.cos();
+    ((r12 * r12 + r23 * r23 + c) / (1.0 + r12 * r12 * r23 * r23 + c)).clamp(0.0, 1.0)
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f32, ior: f32) -> ThinFilm { ThinFilm { thickness, ior, variation: None } }
+
+    pub fn with_variation(self, texture: Arc<dyn Texture>) -> ThinFilm { ThinFilm { variation: Some(texture), ..self } }
+
+    // Thickness at a hit, to work out once and pass to the reflectances below
+    pub fn thickness_at(&self, rec: &Hit) -> f32 {
+        match self.variation {
+            Some(ref t) => {
+                let c = t.value(rec.u, rec.v, rec.p, rec.uv_width);
+                self.thickness * (c.r() + c.g() + c.b()) / 3.0
+            }
+            None => self.thickness
+        }
+    }
+
+    // Reflectance at a wavelength in nanometres, for light arriving at cos_i to the normal from
+    // a side whose index is n1, where the film is thickness thick
+    pub fn reflectance(&self, thickness: f32, cos_i: f32, n1: f32, substrate: Substrate, lambda: f32) -> f32 {
+        let n2 = self.ior;
+        let cos1 = cos_i.clamp(0.0, 1.0);
+        let sin2_1 = 1.0 - cos1 * cos1;
+
+        let cos2_2 = 1.0 - (n1 / n2) * (n1 / n2) * sin2_1;
+        if cos2_2 <= 0.0 {
+            // Totally reflected at the top of the film
+            return 1.0;
+        }
+        let cos2 = cos2_2.sqrt();
+        let r12s = (n1 * cos1 - n2 * cos2) / (n1 * cos1 + n2 * cos2);
+        let r12p = (n2 * cos1 - n1 * cos2) / (n2 * cos1 + n1 * cos2);
+
+        let (r23s, r23p) = match substrate {
+            Substrate::Dielectric(n3) => {
+                let cos2_3 = 1.0 - (n1 / n3) * (n1 / n3) * sin2_1;
+                if cos2_3 <= 0.0 {
+                    return 1.0;
+                }
+                let cos3 = cos2_3.sqrt();
+                ((n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3), (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3))
+            }
+            Substrate::Metal(r) => (-r.max(0.0).sqrt(), -r.max(0.0).sqrt())
+        };
+
+        // Extra path length of a round trip through the film, as a phase
+        let phase = 4.0 * PI * n2 * thickness * cos2 / lambda;
+        0.5 * (airy(r12s, r23s, phase) + airy(r12p, r23p, phase))
+    }
+
+    // Reflectance in RGB, averaged over the wavelengths each channel sees, with the substrate
+    // given for each channel
+    pub fn reflectance_rgb<F: Fn(usize) -> Substrate>(&self, thickness: f32, cos_i: f32, n1: f32, substrate: F) -> Vec3 {
+        let mut total = Vec3::zero();
+        for &(lambda, weight) in rgb_samples() {
+            match substrate(0) {
+                Substrate::Dielectric(_) => total += weight * self.reflectance(thickness, cos_i, n1, substrate(0), lambda),
+                Substrate::Metal(_) => for c in 0..3 {
+                    total[c] += weight[c] * self.reflectance(thickness, cos_i, n1, substrate(c), lambda);
+                }
+            }
+        }
+        total
+    }
+}
@@ -1,74 +1,212 @@
 extern crate rand;
 
+use std::f32;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
 use vec3::*;
 use ray::*;
 use material::*;
+use aabb::*;
+
+pub trait Hitable: Send + Sync {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>>;
+
+    // Bounds of the object, or None if it is unbounded
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb>;
+
+    // Objects that give off light can be sampled directly from a point o:
+    //   random() - a direction from o towards the object
+    //   pdf_value() - solid angle density with which random() would pick direction v
+    fn is_emissive(&self) -> bool { false }
+    fn pdf_value(&self, _o: Vec3, _v: Vec3) -> f32 { 0.0 }
+    fn random(&self, _o: Vec3) -> Vec3 { Vec3::new(1.0, 0.0, 0.0) }
+
+    // Media - the fraction of light along the ray that gets through them between t_min and
+    // t_max. Surfaces let it all through; they are found by hit().
+    fn transmittance(&self, _r: &Ray, _t_min: f32, _t_max: f32) -> f32 { 1.0 }
+}
 
-pub trait Hitable {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit>;
+// Shared objects - lets lights be both in the world and in the scene's list of lights
+//
+impl<T: Hitable + ?Sized> Hitable for Arc<T> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> { (**self).hit(r, t_min, t_max) }
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> { (**self).axis_aligned_bounding_box() }
+    fn is_emissive(&self) -> bool { (**self).is_emissive() }
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f32 { (**self).pdf_value(o, v) }
+    fn random(&self, o: Vec3) -> Vec3 { (**self).random(o) }
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32) -> f32 { (**self).transmittance(r, t_min, t_max) }
+}
+
+// Solid angle density at o of sampling the point hit along v uniformly over a surface of the given area
+//
+pub fn area_pdf(v: Vec3, rec: &Hit, area: f32) -> f32 {
+    let distance_squared = rec.t * rec.t * v.length_squared();
+    let cosine = dot(unit_vector(v), rec.ng).abs();
+    if cosine < 1e-6 || area <= 0.0 {
+        return 0.0;
+    }
+    distance_squared / (cosine * area)
 }
 
 pub struct Sphere {
     centre: Vec3,
     radius: f32,
-    material: Box<Material>
+    material: Arc<dyn Material>
 }
 
 impl Sphere {
-    pub fn new(c: Vec3, r: f32, m:Box<Material>) -> Sphere { Sphere { centre:c, radius:r, material:m} }
+    pub fn new(c: Vec3, r: f32, m:Arc<dyn Material>) -> Sphere { Sphere { centre:c, radius:r, material:m} }
+}
+
+fn sphere_hit_at<'a>(centre: Vec3, radius: f32, m: &'a dyn Material, r: &Ray, t: f32) -> Hit<'a> {
+    let p = r.point_at_parameter(t);
+    let n = (p - centre) / radius;
+
+    // Longitude and latitude, from the outward direction whichever way the normal faces
+    let d = (p - centre) / radius.abs();
+    let theta = (-d.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-d.z()).atan2(d.x()) + PI;
+
+    // A unit step in u is a circle of latitude, and in v half a circle of longitude
+    let r_abs = radius.abs();
+    let du = 2.0 * PI * r_abs * theta.sin().max(0.01);
+    let dv = PI * r_abs;
+    let uv_width = r.footprint(t) / (du * dv).sqrt();
+
+    Hit { t, p, n, ng: n, u: phi / (2.0 * PI), v: theta / PI, uv_width, m }
+}
+
+fn hit_sphere<'a>(centre: Vec3, radius: f32, m: &'a dyn Material, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'a>> {
+    let oc = r.origin() - centre;
+    let a = dot(r.direction(), r.direction());
+    let b = 2.0 * dot(oc, r.direction());
+    let c = dot(oc, oc) - radius * radius;
+    let discriminant = b*b - 4.0*a*c;
+    if discriminant > 0.0 {
+        let temp = (-b - discriminant.sqrt()) / (2.0*a);
+        if temp < t_max && temp > t_min {
+            return Some(sphere_hit_at(centre, radius, m, r, temp));
+        }
+
+        let temp = (-b + discriminant.sqrt()) / (2.0*a);
+        if temp < t_max && temp > t_min {
+            return Some(sphere_hit_at(centre, radius, m, r, temp));
+        }
+    }
+
+    None
 }
 
 impl Hitable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
-        let oc = r.origin() - self.centre;
-        let a = dot(r.direction(), r.direction());
-        let b = 2.0 * dot(oc, r.direction());
-        let c = dot(oc, oc) - self.radius * self.radius;
-        let discriminant = b*b - 4.0*a*c;
-        if discriminant > 0.0 {
-            let temp = (-b - discriminant.sqrt()) / (2.0*a);
-            if temp < t_max && temp > t_min {
-                let p = r.point_at_parameter(temp);
-                return Some(Hit::new(temp, p, (p - self.centre) / self.radius, &self.material));
-            }
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        hit_sphere(self.centre, self.radius, self.material.as_ref(), r, t_min, t_max)
+    }
 
-            let temp = (-b + discriminant.sqrt()) / (2.0*a);
-            if temp < t_max && temp > t_min {
-                let p = r.point_at_parameter(temp);
-                return Some(Hit::new(temp, p, (p - self.centre) / self.radius, &self.material));
-            }
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        Some(Aabb::new(self.centre - r, self.centre + r))
+    }
+
+    fn is_emissive(&self) -> bool { self.material.is_emissive() }
+
+    // From outside, sample the cone the sphere subtends. From inside, sample its surface.
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f32 {
+        let rec = match self.hit(&Ray::new(o, v), 0.001, f32::MAX) {
+            Some(rec) => rec,
+            None => return 0.0
+        };
+
+        let r2 = self.radius * self.radius;
+        let d2 = (self.centre - o).length_squared();
+        if d2 <= r2 {
+            return area_pdf(v, &rec, 4.0 * PI * r2);
+        }
+
+        let cos_theta_max = (1.0 - r2 / d2).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, o: Vec3) -> Vec3 {
+        let r2 = self.radius * self.radius;
+        let d2 = (self.centre - o).length_squared();
+        if d2 <= r2 {
+            return self.centre + self.radius.abs() * random_unit_vector() - o;
         }
 
-        None
+        let w = unit_vector(self.centre - o);
+        let (u, v) = orthonormal_basis(w);
+        let r1 = rand();
+        let z = 1.0 + rand() * ((1.0 - r2 / d2).sqrt() - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+        phi.cos() * sin_theta * u + phi.sin() * sin_theta * v + z * w
+    }
+}
+
+// Sphere moving in a straight line, from centre0 at time 0 to centre1 at time 1
+//
+// Where it is depends on the time, which light sampling does not know, so it is never
+// sampled as a light - it still glows when hit.
+//
+pub struct MovingSphere {
+    centre0: Vec3,
+    centre1: Vec3,
+    radius: f32,
+    material: Arc<dyn Material>
+}
+
+impl MovingSphere {
+    pub fn new(c0: Vec3, c1: Vec3, r: f32, m: Arc<dyn Material>) -> MovingSphere { MovingSphere { centre0:c0, centre1:c1, radius:r, material:m } }
+
+    fn centre(&self, time: f32) -> Vec3 {
+        self.centre0 + time * (self.centre1 - self.centre0)
+    }
+}
+
+impl Hitable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        hit_sphere(self.centre(r.time()), self.radius, self.material.as_ref(), r, t_min, t_max)
+    }
+
+    // Everywhere it is between times 0 and 1 - any shutter is expected to be within that
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius.abs(), self.radius.abs(), self.radius.abs());
+        Some(surrounding_box(Aabb::new(self.centre0 - r, self.centre0 + r), Aabb::new(self.centre1 - r, self.centre1 + r)))
     }
 }
 
 pub struct HitableList {
-    list: Vec<Box<Hitable>>
+    list: Vec<Box<dyn Hitable>>
 }
 
 impl HitableList {
-    pub fn new(l:Vec<Box<Hitable>>) -> HitableList { HitableList { list:l } }
-    pub fn push(&mut self, h: Box<Hitable>) { self.list.push(h); }
+    pub fn new(l:Vec<Box<dyn Hitable>>) -> HitableList { HitableList { list:l } }
+    pub fn push(&mut self, h: Box<dyn Hitable>) { self.list.push(h); }
+    pub fn into_vec(self) -> Vec<Box<dyn Hitable>> { self.list }
 }
 
 impl Hitable for HitableList {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
         let mut temp_rec = None;
         let mut closest_so_far = t_max;
 
         for h in &self.list {
-            match h.hit(r, t_min, closest_so_far) {
-                Some(rec) => {
-                    closest_so_far = rec.t;
-                    temp_rec = Some(rec);
-                }
-                None => {
-                }
+            if let Some(rec) = h.hit(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                temp_rec = Some(rec);
             }
         }
         
         temp_rec
     }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        let mut bbox = Aabb::empty();
+        for h in &self.list {
+            bbox = surrounding_box(bbox, h.axis_aligned_bounding_box()?);
+        }
+        Some(bbox)
+    }
 }
 
@@ -0,0 +1,155 @@
+use std::f32;
+
+use vec3::*;
+use ray::*;
+use material::*;
+use hitable::*;
+use scene::*;
+use spectrum::*;
+
+// Path tracing with next event estimation
+//
+// At each non-specular bounce a light is sampled directly with a shadow ray, as well as the
+// material choosing a direction to continue the path in. Either strategy can find the same
+// light, so both are weighted by the power heuristic on their two densities.
+//
+
+fn power_heuristic(pdf_a: f32, pdf_b: f32) -> f32 {
+    let a2 = pdf_a * pdf_a;
+    let b2 = pdf_b * pdf_b;
+    if a2 + b2 > 0.0 { a2 / (a2 + b2) } else { 0.0 }
+}
+
+fn is_black(c: Vec3) -> bool {
+    c.r() <= 0.0 && c.g() <= 0.0 && c.b() <= 0.0
+}
+
+// A spectral path's wavelengths, or None for an RGB one
+type Wavelengths = Option<[f32; 3]>;
+
+// Background seen along a direction, in the path's colours
+fn background(scene: &Scene, d: Vec3, lambdas: Wavelengths) -> Vec3 {
+    let c = scene.background.value(d);
+    match lambdas {
+        Some(l) => rgb_to_spectrum(c, l),
+        None => c
+    }
+}
+
+// Light given off by what a ray hit, in the path's colours
+fn emitted(r: &Ray, rec: &Hit, lambdas: Wavelengths) -> Vec3 {
+    match lambdas {
+        Some(l) => rec.m.emitted_spectrum(r, rec, l),
+        None => rec.m.emitted(r, rec)
+    }
+}
+
+// An attenuation or eval() towards wi, in the path's colours
+fn reflected(r: &Ray, rec: &Hit, wi: Vec3, value: Vec3, lambdas: Wavelengths) -> Vec3 {
+    match lambdas {
+        Some(l) => rec.m.to_spectrum(r, rec, wi, value, l),
+        None => value
+    }
+}
+
+// MIS weight for light found by following the material's choice of direction
+fn bsdf_weight(scene: &Scene, r: &Ray, bsdf_pdf: f32) -> f32 {
+    if bsdf_pdf > 0.0 { power_heuristic(bsdf_pdf, scene.light_pdf(r.origin(), r.direction())) } else { 1.0 }
+}
+
+// Light reaching the hit by sampling a light source, MIS weighted against the material
+//
+fn sample_lights(scene: &Scene, r_in: &Ray, rec: &Hit, lambdas: Wavelengths) -> Vec3 {
+    let wi = scene.random_light_direction(rec.p);
+    let light_pdf = scene.light_pdf(rec.p, wi);
+    if light_pdf <= 0.0 {
+        return Vec3::zero();
+    }
+
+    let f = rec.m.eval(r_in, rec, wi);
+    if is_black(f) {
+        return Vec3::zero();
+    }
+    let f = reflected(r_in, rec, wi, f, lambdas);
+    if is_black(f) {
+        return Vec3::zero();
+    }
+
+    // Whatever surface the shadow ray hits first is what was sampled, dimmed by any media on the way
+    let shadow = Ray::new(rec.p, wi).with_time(r_in.time()).with_wavelength(r_in.wavelength());
+    let (emitted, t) = match scene.world.hit(&shadow, 0.001, f32::MAX) {
+        Some(l) => (emitted(&shadow, &l, lambdas), l.t),
+        None => (background(scene, wi, lambdas), f32::MAX)
+    };
+    if is_black(emitted) {
+        return Vec3::zero();
+    }
+    let emitted = emitted * scene.transmittance(&shadow, 0.001, t);
+
+    let weight = power_heuristic(light_pdf, rec.m.pdf(r_in, rec, wi));
+    emitted * f * (weight / light_pdf)
+}
+
+// Light arriving along r - in RGB, or at the wavelengths of a spectral path if the camera ray
+// comes with a hero wavelength
+//
+pub fn colour(r: &Ray, scene: &Scene, max_depth: usize) -> Vec3 {
+    let lambdas = if r.wavelength() > 0.0 { Some(hero_wavelengths(r.wavelength())) } else { None };
+    let mut r = *r;
+    let mut result = Vec3::zero();
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+
+    // Density of the bounce that made r - zero for camera rays and specular bounces,
+    // where lights were not sampled, so anything hit counts in full
+    let mut bsdf_pdf = 0.0;
+    let mut hero_only = false;
+
+    for depth in 0.. {
+        let rec = match scene.hit(&r, 0.001, f32::MAX) {
+            Some(rec) => rec,
+            None => {
+                result += throughput * background(scene, r.direction(), lambdas) * bsdf_weight(scene, &r, bsdf_pdf);
+                break;
+            }
+        };
+
+        // Only emissive materials can be found by sampling lights - anything else that glows,
+        // like a medium, is only found here and counts in full
+        let emitted = emitted(&r, &rec, lambdas);
+        if !is_black(emitted) {
+            let weight = if rec.m.is_emissive() { bsdf_weight(scene, &r, bsdf_pdf) } else { 1.0 };
+            result += throughput * emitted * weight;
+        }
+
+        if depth > max_depth {
+            break;
+        }
+
+        // Lights are sampled at any bounce that is not a mirror's, even where scatter() absorbs
+        // the path - the light sample is an estimate in its own right
+        let s = rec.m.scatter(&r, &rec);
+        if s.as_ref().is_none_or(|s| s.pdf > 0.0) && scene.light_count() > 0 {
+            result += throughput * sample_lights(scene, &r, &rec, lambdas);
+        }
+
+        let s = match s {
+            Some(s) => s,
+            None => break
+        };
+
+        throughput *= reflected(&r, &rec, s.scattered.direction(), s.attenuation, lambdas);
+
+        // Past anything dispersive only the hero wavelength can go on, standing in for all three
+        if lambdas.is_some() && rec.m.is_dispersive() && !hero_only {
+            throughput = Vec3::new(3.0 * throughput[0], 0.0, 0.0);
+            hero_only = true;
+        }
+        bsdf_pdf = s.pdf;
+        // A wavelength, once picked, stays with the path, as do the dielectrics it is inside
+        let wavelength = if s.scattered.wavelength() > 0.0 { s.scattered.wavelength() } else { r.wavelength() };
+        let interiors = if s.scattered.has_interiors() { s.scattered.interiors() } else { r.interiors() };
+        r = s.scattered.with_time(r.time()).with_wavelength(wavelength).with_interiors(interiors);
+    }
+
+    result
+}
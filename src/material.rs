@@ -1,76 +1,343 @@
 
+use std::f32::consts::PI;
+use std::sync::{Arc, Once};
+
 use vec3::*;
 use ray::*;
+use texture::*;
+use noise::*;
+use spectrum::*;
+use film::*;
 
 #[derive(Copy, Clone)]
 pub struct Hit<'a> {
     pub t: f32,
     pub p: Vec3,
-    pub n: Vec3,
-    pub m: &'a Box<Material>
+    pub n: Vec3,        // Shading normal
+    pub ng: Vec3,       // Geometric normal
+    pub u: f32,
+    pub v: f32,
+    pub uv_width: f32,  // Width of the ray's footprint in (u, v) - zero to point sample textures
+    pub m: &'a dyn Material
 }
 
 impl<'a> Hit<'a> {
-    pub fn new(nt: f32, np: Vec3, nn: Vec3, mm: &'a Box<Material>) -> Hit<'a> { Hit { t:nt, p:np, n:nn, m:mm } }
+    pub fn new(nt: f32, np: Vec3, nn: Vec3, mm: &'a dyn Material) -> Hit<'a> { Hit { t:nt, p:np, n:nn, ng:nn, u:0.0, v:0.0, uv_width:0.0, m:mm } }
+
+    // Shading normal flipped, if need be, onto the side the ray came from
+    pub fn facing_normal(&self, r_in: &Ray) -> Vec3 {
+        if dot(r_in.direction(), self.n) > 0.0 { -self.n } else { self.n }
+    }
 }
 
 pub struct Scattered {
     pub scattered: Ray,
-    pub attenuation: Vec3
+    pub attenuation: Vec3,  // BSDF * cosine / pdf
+    pub pdf: f32            // Solid angle density of the scattered direction - zero if specular
 }
 
-pub trait Material {
+// Scatter picks a direction to follow. Materials that are not purely specular also
+// describe their BSDF with eval() and pdf(), so that light sources can be sampled directly,
+// and the two weighted against each other.
+//
+pub trait Material: Send + Sync {
     fn scatter(&self, r_in: &Ray, rec: &Hit) -> Option<Scattered>;
+
+    // BSDF * cosine for light arriving along direction wi (pointing away from the hit)
+    fn eval(&self, _r_in: &Ray, _rec: &Hit, _wi: Vec3) -> Vec3 { Vec3::zero() }
+
+    // Solid angle density with which scatter() would choose wi
+    fn pdf(&self, _r_in: &Ray, _rec: &Hit, _wi: Vec3) -> f32 { 0.0 }
+
+    // Light given off at the hit, towards the ray's origin
+    fn emitted(&self, _r_in: &Ray, _rec: &Hit) -> Vec3 { Vec3::zero() }
+
+    fn is_emissive(&self) -> bool { false }
+
+    // What this material gave in RGB - an attenuation, or eval() towards wi - at the wavelengths
+    // of a spectral path. Upsampled, unless the material knows its spectrum better.
+    fn to_spectrum(&self, _r_in: &Ray, _rec: &Hit, _wi: Vec3, value: Vec3, lambdas: [f32; 3]) -> Vec3 {
+        rgb_to_spectrum(value, lambdas)
+    }
+
+    // emitted(), at the wavelengths of a spectral path
+    fn emitted_spectrum(&self, r_in: &Ray, rec: &Hit, lambdas: [f32; 3]) -> Vec3 {
+        rgb_to_spectrum(self.emitted(r_in, rec), lambdas)
+    }
+
+    // Whether the way the material scatters depends on wavelength, so that a spectral path can
+    // only go on at its hero wavelength
+    fn is_dispersive(&self) -> bool { false }
 }
 
 //  Lambertian
 //
 pub struct Lambertian {
-    albedo: Vec3
+    albedo: Arc<dyn Texture>
 }
 
 impl Lambertian {
-    pub fn new(a : Vec3) -> Lambertian { Lambertian { albedo: a } }
+    pub fn new(a : Vec3) -> Lambertian { Lambertian { albedo: Arc::new(SolidColour::new(a)) } }
+    pub fn textured(a: Arc<dyn Texture>) -> Lambertian { Lambertian { albedo: a } }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _r_in: &Ray, rec: &Hit) -> Option<Scattered> {
-        let target = rec.p + rec.n + random_in_unit_sphere();
-        Some(Scattered { scattered:Ray::new(rec.p, target - rec.p), attenuation:self.albedo })
+    fn scatter(&self, r_in: &Ray, rec: &Hit) -> Option<Scattered> {
+        // Cosine weighted, so the attenuation is just the albedo
+        let n = rec.facing_normal(r_in);
+        let mut direction = n + random_unit_vector();
+        if direction.length_squared() < 1e-8 {
+            direction = n;
+        }
+        let pdf = dot(n, unit_vector(direction)) / PI;
+        Some(Scattered { scattered:Ray::new(rec.p, direction), attenuation:self.albedo.value(rec.u, rec.v, rec.p, rec.uv_width), pdf })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> Vec3 {
+        let cosine = dot(rec.facing_normal(r_in), unit_vector(wi)).max(0.0);
+        self.albedo.value(rec.u, rec.v, rec.p, rec.uv_width) * (cosine / PI)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> f32 {
+        dot(rec.facing_normal(r_in), unit_vector(wi)).max(0.0) / PI
     }
 }
 
-// Metal
+// Metal, optionally under a thin film, which reflects the albedo through it
 //
 pub struct Metal {
-    albedo: Vec3,
-    fuzz: f32
+    albedo: Arc<dyn Texture>,
+    fuzz: f32,
+    film: Option<ThinFilm>
 }
 
 impl Metal {
-    pub fn new(a : Vec3, f: f32) -> Metal { Metal { albedo:a, fuzz:f } }
+    pub fn new(a : Vec3, f: f32) -> Metal { Metal { albedo:Arc::new(SolidColour::new(a)), fuzz:f, film:None } }
+    pub fn textured(a: Arc<dyn Texture>, f: f32) -> Metal { Metal { albedo:a, fuzz:f, film:None } }
+
+    pub fn with_film(self, film: ThinFilm) -> Metal { Metal { film: Some(film), ..self } }
+}
+
+// Cosine of the angle to the normal of the facet that reflected r_in into wi
+fn reflection_cosine(r_in: &Ray, wi: Vec3) -> f32 {
+    let wo = -unit_vector(r_in.direction());
+    let h = wo + unit_vector(wi);
+    if h.length_squared() > 0.0 { dot(wo, unit_vector(h)) } else { 0.0 }
 }
 
 impl Material for Metal {
     fn scatter(&self, r_in: &Ray, rec: &Hit) -> Option<Scattered> {
-        let s = Ray::new(rec.p, reflect(unit_vector(r_in.direction()), rec.n) + self.fuzz * random_in_unit_sphere());
+        // Both sides reflect, as planes, rects and disks are hit from behind as well
+        let n = rec.facing_normal(r_in);
+        let s = Ray::new(rec.p, reflect(unit_vector(r_in.direction()), n) + self.fuzz * random_in_unit_sphere());
 
-        if dot(s.direction(), rec.n) > 0.0 {
-            Some(Scattered { scattered:s, attenuation:self.albedo })
+        if dot(s.direction(), n) > 0.0 {
+            let albedo = self.albedo.value(rec.u, rec.v, rec.p, rec.uv_width);
+            let attenuation = match self.film {
+                Some(ref film) => film.reflectance_rgb(film.thickness_at(rec), reflection_cosine(r_in, s.direction()), 1.0, |c| Substrate::Metal(albedo[c])),
+                None => albedo
+            };
+            Some(Scattered { scattered:s, attenuation, pdf:0.0 })
         } else {
             None
         }
     }
+
+    fn to_spectrum(&self, r_in: &Ray, rec: &Hit, wi: Vec3, value: Vec3, lambdas: [f32; 3]) -> Vec3 {
+        let film = match self.film {
+            Some(ref film) => film,
+            None => return rgb_to_spectrum(value, lambdas)
+        };
+        let albedo = rgb_to_spectrum(self.albedo.value(rec.u, rec.v, rec.p, rec.uv_width), lambdas);
+        let (thickness, cos_i) = (film.thickness_at(rec), reflection_cosine(r_in, wi));
+        let at = |i: usize| film.reflectance(thickness, cos_i, 1.0, Substrate::Metal(albedo[i].min(1.0)), lambdas[i]);
+        Vec3::new(at(0), at(1), at(2))
+    }
+}
+
+// Bump mapping - tilts the shading normal of another material down the slope of a
+// procedural height field
+//
+pub struct Bump {
+    base: Arc<dyn Material>,
+    height: Procedural,
+    strength: f32
+}
+
+impl Bump {
+    pub fn new(base: Arc<dyn Material>, height: Procedural, strength: f32) -> Bump { Bump { base, height, strength } }
+
+    fn bumped<'a>(&self, rec: &Hit<'a>) -> Hit<'a> {
+        let g = self.strength * self.height.gradient(rec.p);
+        let n = unit_vector(rec.n - (g - dot(g, rec.n) * rec.n));
+        Hit { n, ..*rec }
+    }
+}
+
+impl Material for Bump {
+    fn scatter(&self, r_in: &Ray, rec: &Hit) -> Option<Scattered> {
+        self.base.scatter(r_in, &self.bumped(rec))
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> Vec3 {
+        self.base.eval(r_in, &self.bumped(rec), wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> f32 {
+        self.base.pdf(r_in, &self.bumped(rec), wi)
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &Hit) -> Vec3 {
+        self.base.emitted(r_in, &self.bumped(rec))
+    }
+
+    fn is_emissive(&self) -> bool { self.base.is_emissive() }
+
+    fn to_spectrum(&self, r_in: &Ray, rec: &Hit, wi: Vec3, value: Vec3, lambdas: [f32; 3]) -> Vec3 {
+        self.base.to_spectrum(r_in, &self.bumped(rec), wi, value, lambdas)
+    }
+
+    fn emitted_spectrum(&self, r_in: &Ray, rec: &Hit, lambdas: [f32; 3]) -> Vec3 {
+        self.base.emitted_spectrum(r_in, &self.bumped(rec), lambdas)
+    }
+
+    fn is_dispersive(&self) -> bool { self.base.is_dispersive() }
+}
+
+// Dispersion - how a glass's index of refraction changes with wavelength
+//
+#[derive(Copy, Clone)]
+pub enum Dispersion {
+    None,
+    Cauchy { b: f32 },                          // n = A + B / lambda^2, lambda in micrometres, through the ior
+    Sellmeier { b: [f32; 3], c: [f32; 3] }      // n^2 = 1 + sum of B lambda^2 / (lambda^2 - C), ignoring the ior
+}
+
+impl Dispersion {
+    // Sellmeier coefficients of some common glasses
+    pub fn named(name: &str) -> Option<Dispersion> {
+        let (b, c) = match name {
+            "bk7" => ([1.039612, 0.2317923, 1.010469], [0.006000699, 0.02001791, 103.5607]),
+            "sf11" => ([1.737597, 0.3137473, 1.898781], [0.01318871, 0.06230681, 155.2363]),
+            "fused_silica" => ([0.6961663, 0.4079426, 0.8974794], [0.004679148, 0.01351206, 97.934]),
+            _ => return None
+        };
+        Some(Dispersion::Sellmeier { b, c })
+    }
+
+    // Index at a wavelength in nanometres, for a glass whose index is ior at the sodium D line
+    pub fn index(&self, ior: f32, lambda: f32) -> f32 {
+        let l2 = (lambda / 1000.0) * (lambda / 1000.0);
+        let d2 = (LAMBDA_D / 1000.0) * (LAMBDA_D / 1000.0);
+        match *self {
+            Dispersion::None => ior,
+            Dispersion::Cauchy { b } => ior + b / l2 - b / d2,
+            Dispersion::Sellmeier { b, c } => (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f32>()).sqrt()
+        }
+    }
 }
 
-// Dielectric
+// Dielectric - glass, water, diamond. Coloured glass absorbs along the distance light travels
+// through it (Beer-Lambert), and glass that disperses splits white light into its wavelengths,
+// picking one the first time a ray meets it and following that through the rest of the path.
+//
+// Dielectrics can be nested - liquid in a glass, ice in water, a bubble of air in glass - with
+// the ray keeping track of which it is inside, so each surface bends light by the ratio of the
+// indices either side of it. Where two overlap, the one of higher priority wins.
+//
+// Absorption is measured at each dielectric surface, along the ray from the last one, so
+// anything else inside the glass would hide some of the distance.
+//
+// A thin film on the surface takes over from the Fresnel term, making a soap bubble of a
+// dielectric of index 1 in air.
 //
 pub struct Dielectric {
-    ior: f32
+    ior: f32,
+    absorption: Vec3,       // Per unit distance
+    dispersion: Dispersion,
+    priority: i32,
+    film: Option<ThinFilm>
+}
+
+// How a ray meets a dielectric's surface
+struct Crossing {
+    back: Interiors,        // Those on the ray's side, which it stays in if reflected
+    through: Interiors,     // ... and going on through the surface
+    real: bool,             // False inside something of higher priority
+    outward_normal: Vec3,   // Towards the side the ray comes from
+    n_i: f32,               // Index on the ray's side
+    n_t: f32,               // ... and on the far side
+    cos_i: f32
 }
 
 impl Dielectric {
-    pub fn new(i: f32) -> Dielectric { Dielectric { ior:i } }
+    pub fn new(i: f32) -> Dielectric { Dielectric { ior:i, absorption:Vec3::zero(), dispersion:Dispersion::None, priority:0, film:None } }
+
+    // Glass that leaves light the given colour after passing through the given distance of it
+    pub fn with_absorption(self, colour: Vec3, distance: f32) -> Dielectric {
+        let sigma = |c: f32| -c.clamp(1e-6, 1.0).ln() / distance;
+        Dielectric { absorption: Vec3::new(sigma(colour.r()), sigma(colour.g()), sigma(colour.b())), ..self }
+    }
+
+    // Sellmeier's equation gives the whole index, so its value at the D line replaces the ior
+    pub fn with_dispersion(self, dispersion: Dispersion) -> Dielectric {
+        let ior = match dispersion {
+            Dispersion::Sellmeier { .. } => dispersion.index(self.ior, LAMBDA_D),
+            _ => self.ior
+        };
+        Dielectric { ior, dispersion, ..self }
+    }
+
+    pub fn with_priority(self, priority: i32) -> Dielectric { Dielectric { priority, ..self } }
+
+    pub fn with_film(self, film: ThinFilm) -> Dielectric { Dielectric { film: Some(film), ..self } }
+
+    fn crossing(&self, r_in: &Ray, rec: &Hit, ior: f32) -> Crossing {
+        // Which dielectrics are inside and outside this surface
+        let interiors = r_in.interiors();
+        let this = Interior { id: self as *const Dielectric as usize, ior, absorption: self.absorption, priority: self.priority };
+        let entering = dot(r_in.direction(), rec.n) < 0.0;
+        // Leaving one the ray was never seen to enter, as from a camera inside it, is still a
+        // surface, with whatever the ray is in on the other side
+        let (inner, outer) = if entering || !interiors.contains(this.id) {
+            (deeper(interiors, this), interiors)
+        } else {
+            (interiors, interiors.without(this.id))
+        };
+
+        let outside_ior = outer.current().map_or(1.0, |c| c.ior);
+        let (outward_normal, n_i, n_t) = if entering { (rec.n, outside_ior, ior) } else { (-rec.n, ior, outside_ior) };
+        Crossing {
+            back: if entering { outer } else { inner },
+            through: if entering { inner } else { outer },
+            real: inner.current().map(|c| c.id) == Some(this.id),
+            outward_normal,
+            n_i,
+            n_t,
+            cos_i: -dot(r_in.direction(), outward_normal) / r_in.direction().length()
+        }
+    }
+
+    // Light let through by whatever the ray has come through since the last surface
+    fn transmittance(r_in: &Ray, rec: &Hit) -> Vec3 {
+        match r_in.interiors().current() {
+            Some(inside) => {
+                let distance = rec.t * r_in.direction().length();
+                let a = inside.absorption;
+                Vec3::new((-a.r() * distance).exp(), (-a.g() * distance).exp(), (-a.b() * distance).exp())
+            }
+            None => Vec3::new(1.0, 1.0, 1.0)
+        }
+    }
+}
+
+// Going into a dielectric, or staying where the ray is, with a warning, if that nests them too deep
+fn deeper(interiors: Interiors, interior: Interior) -> Interiors {
+    static WARNING: Once = Once::new();
+    interiors.with(interior).unwrap_or_else(|| {
+        WARNING.call_once(|| eprintln!("rustrace: warning: dielectrics nested more than {} deep, ignoring the innermost", MAX_INTERIORS));
+        interiors
+    })
 }
 
 fn schlick(cosine: f32, ior: f32) -> f32 {
@@ -79,28 +346,160 @@ fn schlick(cosine: f32, ior: f32) -> f32 {
     r0r0 + (1.0-r0r0) * (1.0-cosine).powf(5.0)
 }
 
+// With a film the chance of reflecting is its average reflectance, and what is reflected or
+// transmitted is coloured by how that differs across the spectrum
+fn film_chance(reflectance: Vec3) -> f32 {
+    (reflectance.r() + reflectance.g() + reflectance.b()) / 3.0
+}
+
+fn film_weight(reflectance: f32, chance: f32, reflected: bool) -> f32 {
+    if reflected { reflectance / chance } else { (1.0 - reflectance) / (1.0 - chance) }
+}
+
 impl Material for Dielectric {
     fn scatter(&self, r_in: &Ray, rec: &Hit) -> Option<Scattered> {
-        let attn = Vec3::new(1.0, 1.0, 1.0);
-        let reflected = reflect(r_in.direction(), rec.n);
-        
-        let (outward_normal, ni_over_nt, cosine) = if dot(r_in.direction(), rec.n) > 0.0 {
-            (-rec.n, self.ior, self.ior * dot(r_in.direction(), rec.n) / r_in.direction().length())
-        } else {
-            (rec.n, 1.0 / self.ior, -dot(r_in.direction(), rec.n) / r_in.direction().length())
-        };
+        let mut attn = Dielectric::transmittance(r_in, rec);
 
-        match refract(r_in.direction(), outward_normal, ni_over_nt) {
+        // Picking a wavelength makes the path that colour
+        let mut wavelength = r_in.wavelength();
+        if wavelength == 0.0 && !matches!(self.dispersion, Dispersion::None) {
+            let (lambda, weight) = sample_wavelength();
+            wavelength = lambda;
+            attn *= weight;
+        }
+        let ior = if wavelength > 0.0 { self.dispersion.index(self.ior, wavelength) } else { self.ior };
+        let c = self.crossing(r_in, rec, ior);
+
+        // Inside something of higher priority this surface is not really there
+        if !c.real {
+            let scattered = Ray::new(rec.p, r_in.direction()).with_wavelength(wavelength).with_interiors(c.through);
+            return Some(Scattered { scattered, attenuation: attn, pdf: 0.0 });
+        }
+
+        let reflected = Ray::new(rec.p, reflect(r_in.direction(), rec.n)).with_interiors(c.back);
+        let scattered = match refract(r_in.direction(), c.outward_normal, c.n_i / c.n_t) {
             Some(refracted) => {
-                if rand() >= schlick(cosine, self.ior) {
-                    Some(Scattered { scattered:Ray::new(rec.p, refracted), attenuation: attn})
-                } else {
-                    Some(Scattered { scattered:Ray::new(rec.p, reflected), attenuation: attn})
+                let refracted = Ray::new(rec.p, refracted).with_interiors(c.through);
+                match self.film {
+                    Some(ref film) => {
+                        let r = film.reflectance_rgb(film.thickness_at(rec), c.cos_i, c.n_i, |_| Substrate::Dielectric(c.n_t));
+                        let chance = film_chance(r);
+                        let reflect = rand() < chance;
+                        attn *= Vec3::new(film_weight(r.r(), chance, reflect), film_weight(r.g(), chance, reflect), film_weight(r.b(), chance, reflect));
+                        if reflect { reflected } else { refracted }
+                    }
+                    None => {
+                        // Schlick's approximation goes by the angle on the side of lower index
+                        let (n_i, n_t) = (c.n_i, c.n_t);
+                        let cosine = if n_i > n_t { (1.0 - (n_i / n_t) * (n_i / n_t) * (1.0 - c.cos_i * c.cos_i)).max(0.0).sqrt() } else { c.cos_i };
+                        if rand() >= schlick(cosine, n_t / n_i) { refracted } else { reflected }
+                    }
                 }
             }
-            None => {
-                Some(Scattered { scattered:Ray::new(rec.p, reflected), attenuation: attn})
-            }
+            None => reflected
+        };
+        Some(Scattered { scattered:scattered.with_wavelength(wavelength), attenuation: attn, pdf: 0.0})
+    }
+
+    // The film's colours, worked out again at the path's wavelengths
+    fn to_spectrum(&self, r_in: &Ray, rec: &Hit, wi: Vec3, value: Vec3, lambdas: [f32; 3]) -> Vec3 {
+        let film = match self.film {
+            Some(ref film) => film,
+            None => return rgb_to_spectrum(value, lambdas)
+        };
+        let ior = if r_in.wavelength() > 0.0 { self.dispersion.index(self.ior, r_in.wavelength()) } else { self.ior };
+        let c = self.crossing(r_in, rec, ior);
+        if !c.real || refract(r_in.direction(), c.outward_normal, c.n_i / c.n_t).is_none() {
+            return rgb_to_spectrum(value, lambdas);
         }
+
+        let reflect = dot(wi, c.outward_normal) > 0.0;
+        let thickness = film.thickness_at(rec);
+        let chance = film_chance(film.reflectance_rgb(thickness, c.cos_i, c.n_i, |_| Substrate::Dielectric(c.n_t)));
+        let at = |i: usize| film_weight(film.reflectance(thickness, c.cos_i, c.n_i, Substrate::Dielectric(c.n_t), lambdas[i]), chance, reflect);
+        rgb_to_spectrum(Dielectric::transmittance(r_in, rec), lambdas) * Vec3::new(at(0), at(1), at(2))
+    }
+
+    fn is_dispersive(&self) -> bool { !matches!(self.dispersion, Dispersion::None) }
+}
+
+// Diffuse light - emits the same in all directions, and reflects nothing
+//
+pub struct DiffuseLight {
+    emit: Vec3
+}
+
+impl DiffuseLight {
+    pub fn new(e: Vec3) -> DiffuseLight { DiffuseLight { emit:e } }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &Hit) -> Option<Scattered> {
+        None
+    }
+
+    fn emitted(&self, _r_in: &Ray, _rec: &Hit) -> Vec3 {
+        self.emit
+    }
+
+    fn is_emissive(&self) -> bool { true }
+}
+
+// Blackbody - a light the colour of something glowing at a temperature in kelvin, like 2700 for
+// a tungsten bulb or 6500 for daylight, as bright as a white light of the given strength
+//
+pub struct Blackbody {
+    kelvin: f32,
+    scale: f32,
+    emit: Vec3
+}
+
+impl Blackbody {
+    pub fn new(kelvin: f32, strength: f32) -> Blackbody {
+        let rgb = integrate_rgb(|lambda| planck(lambda, kelvin));
+        let scale = strength / (0.2126 * rgb.r() + 0.7152 * rgb.g() + 0.0722 * rgb.b());
+        Blackbody { kelvin, scale, emit: scale * rgb }
+    }
+}
+
+impl Material for Blackbody {
+    fn scatter(&self, _r_in: &Ray, _rec: &Hit) -> Option<Scattered> {
+        None
+    }
+
+    fn emitted(&self, _r_in: &Ray, _rec: &Hit) -> Vec3 {
+        self.emit
+    }
+
+    fn emitted_spectrum(&self, _r_in: &Ray, _rec: &Hit, lambdas: [f32; 3]) -> Vec3 {
+        self.scale * Vec3::new(planck(lambdas[0], self.kelvin), planck(lambdas[1], self.kelvin), planck(lambdas[2], self.kelvin))
+    }
+
+    fn is_emissive(&self) -> bool { true }
+}
+
+// Isotropic - the phase function of a medium that scatters equally in all directions,
+// with no surface to take a cosine against
+//
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>
+}
+
+impl Isotropic {
+    pub fn new(a: Vec3) -> Isotropic { Isotropic { albedo: Arc::new(SolidColour::new(a)) } }
+    pub fn textured(a: Arc<dyn Texture>) -> Isotropic { Isotropic { albedo: a } }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, _r_in: &Ray, rec: &Hit) -> Option<Scattered> {
+        Some(Scattered { scattered:Ray::new(rec.p, random_unit_vector()), attenuation:self.albedo.value(rec.u, rec.v, rec.p, rec.uv_width), pdf: 1.0 / (4.0 * PI) })
+    }
+
+    fn eval(&self, _r_in: &Ray, rec: &Hit, _wi: Vec3) -> Vec3 {
+        self.albedo.value(rec.u, rec.v, rec.p, rec.uv_width) / (4.0 * PI)
+    }
+
+    fn pdf(&self, _r_in: &Ray, _rec: &Hit, _wi: Vec3) -> f32 {
+        1.0 / (4.0 * PI)
     }
 }
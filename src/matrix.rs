@@ -0,0 +1,265 @@
+use std::ops::Mul;
+
+use vec3::*;
+
+// 4x4 matrix for affine transforms, acting on column vectors - a * b applies b first
+//
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    m: [[f32; 4]; 4]        // Rows
+}
+
+impl Mat4 {
+    pub fn new(m: [[f32; 4]; 4]) -> Mat4 { Mat4 { m } }
+
+    pub fn identity() -> Mat4 {
+        Mat4::new([[1.0, 0.0, 0.0, 0.0],
+                   [0.0, 1.0, 0.0, 0.0],
+                   [0.0, 0.0, 1.0, 0.0],
+                   [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    pub fn translate(t: Vec3) -> Mat4 {
+        Mat4::new([[1.0, 0.0, 0.0, t.x()],
+                   [0.0, 1.0, 0.0, t.y()],
+                   [0.0, 0.0, 1.0, t.z()],
+                   [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    pub fn scale(s: Vec3) -> Mat4 {
+        Mat4::new([[s.x(), 0.0, 0.0, 0.0],
+                   [0.0, s.y(), 0.0, 0.0],
+                   [0.0, 0.0, s.z(), 0.0],
+                   [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    // Anticlockwise looking down the axis towards the origin
+    pub fn rotate(degrees: f32, axis: Vec3) -> Mat4 {
+        let a = unit_vector(axis);
+        let (s, c) = degrees.to_radians().sin_cos();
+        let t = 1.0 - c;
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        Mat4::new([[t * x * x + c,     t * x * y - s * z, t * x * z + s * y, 0.0],
+                   [t * x * y + s * z, t * y * y + c,     t * y * z - s * x, 0.0],
+                   [t * x * z - s * y, t * y * z + s * x, t * z * z + c,     0.0],
+                   [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    // Element by element blend, from a at t = 0 to b at t = 1 - right for scales, but not for
+    // rotations, which it squashes part way
+    pub fn lerp(a: Mat4, b: Mat4, t: f32) -> Mat4 {
+        let mut r = a.m;
+        for (row, brow) in r.iter_mut().zip(b.m.iter()) {
+            for (e, be) in row.iter_mut().zip(brow.iter()) {
+                *e += t * (be - *e);
+            }
+        }
+        Mat4::new(r)
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut t = [[0.0; 4]; 4];
+        for (i, row) in t.iter_mut().enumerate() {
+            for (j, e) in row.iter_mut().enumerate() {
+                *e = self.m[j][i];
+            }
+        }
+        Mat4::new(t)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, or None if the matrix is singular
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs())).unwrap_or(col);
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let p = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= p;
+                inv[col][j] *= p;
+            }
+            for i in 0..4 {
+                if i != col {
+                    let f = a[i][col];
+                    for j in 0..4 {
+                        a[i][j] -= f * a[col][j];
+                        inv[i][j] -= f * inv[col][j];
+                    }
+                }
+            }
+        }
+
+        Some(Mat4::new(inv))
+    }
+
+    // Determinant of the 3x3 linear part - how much volumes are scaled
+    pub fn determinant3(&self) -> f32 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1]) -
+        m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0]) +
+        m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // Split into a translation, then a rotation, then a scale and any shear - a polar
+    // decomposition of the linear part (Shoemake and Duff, "Matrix Animation and Polar
+    // Decomposition", 1992). None if the matrix is singular.
+    pub fn decompose(&self) -> Option<Decomposed> {
+        let translation = Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3]);
+        let mut linear = *self;
+        for row in 0..3 {
+            linear.m[row][3] = 0.0;
+        }
+
+        // Averaging with the inverse transpose converges on the nearest orthogonal matrix
+        let mut r = linear;
+        for _ in 0..100 {
+            let it = r.inverse()?.transpose();
+            let mut change: f32 = 0.0;
+            for i in 0..3 {
+                for j in 0..3 {
+                    let next = 0.5 * (r.m[i][j] + it.m[i][j]);
+                    change = change.max((next - r.m[i][j]).abs());
+                    r.m[i][j] = next;
+                }
+            }
+            if change < 1e-6 {
+                break;
+            }
+        }
+
+        // A mirror image goes in the scale, leaving a proper rotation
+        if r.determinant3() < 0.0 {
+            for row in r.m.iter_mut().take(3) {
+                for e in row.iter_mut().take(3) {
+                    *e = -*e;
+                }
+            }
+        }
+        Some(Decomposed { translation, rotation: Quaternion::from_matrix(&r), scale: r.transpose() * linear })
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+                  m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+                  m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3])
+    }
+
+    // Directions ignore the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+                  m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+                  m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z())
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut r = [[0.0; 4]; 4];
+        for (i, row) in r.iter_mut().enumerate() {
+            for (j, e) in row.iter_mut().enumerate() {
+                *e = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Mat4::new(r)
+    }
+}
+
+// Rotation as a unit quaternion, for turning smoothly from one orientation to another
+//
+#[derive(Copy, Clone, Debug)]
+pub struct Quaternion {
+    w: f32,
+    v: Vec3
+}
+
+impl Quaternion {
+    // From a rotation matrix - orthonormal, with a determinant of 1
+    pub fn from_matrix(r: &Mat4) -> Quaternion {
+        let m = &r.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let (w, x, y, z) = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            (0.25 * s, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            ((m[2][1] - m[1][2]) / s, 0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            ((m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s)
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            ((m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s)
+        };
+        Quaternion { w, v: Vec3::new(x, y, z) }.normalised()
+    }
+
+    fn normalised(&self) -> Quaternion {
+        let length = (self.w * self.w + self.v.length_squared()).sqrt();
+        Quaternion { w: self.w / length, v: self.v / length }
+    }
+
+    fn dot(a: &Quaternion, b: &Quaternion) -> f32 {
+        a.w * b.w + dot(a.v, b.v)
+    }
+
+    pub fn to_matrix(&self) -> Mat4 {
+        let (w, x, y, z) = (self.w, self.v.x(), self.v.y(), self.v.z());
+        Mat4::new([[1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z),       2.0 * (x * z + w * y),       0.0],
+                   [2.0 * (x * y + w * z),       1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x),       0.0],
+                   [2.0 * (x * z - w * y),       2.0 * (y * z + w * x),       1.0 - 2.0 * (x * x + y * y), 0.0],
+                   [0.0, 0.0, 0.0, 1.0]])
+    }
+
+    // Angle, in radians, of the rotation taking a to b the short way round
+    pub fn angle_between(a: &Quaternion, b: &Quaternion) -> f32 {
+        2.0 * Quaternion::dot(a, b).abs().min(1.0).acos()
+    }
+
+    // From a at t = 0 to b at t = 1 the short way round, turning at a steady rate
+    pub fn slerp(a: &Quaternion, b: &Quaternion, t: f32) -> Quaternion {
+        let cos = Quaternion::dot(a, b);
+        let (b, cos) = if cos < 0.0 { (Quaternion { w: -b.w, v: -b.v }, -cos) } else { (*b, cos) };
+        let (wa, wb) = if cos > 0.9995 {
+            // Nearly the same, where a straight blend is as good and better behaved
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            (((1.0 - t) * theta).sin() / theta.sin(), (t * theta).sin() / theta.sin())
+        };
+        Quaternion { w: wa * a.w + wb * b.w, v: wa * a.v + wb * b.v }.normalised()
+    }
+}
+
+// An affine transform as the translation, rotation and scale of Mat4::decompose()
+//
+#[derive(Copy, Clone, Debug)]
+pub struct Decomposed {
+    pub translation: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Mat4         // Scale and any shear, applied first
+}
+
+impl Decomposed {
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::translate(self.translation) * self.rotation.to_matrix() * self.scale
+    }
+
+    // From a at t = 0 to b at t = 1: translation and scale blend, and the rotation turns
+    pub fn interpolate(a: &Decomposed, b: &Decomposed, t: f32) -> Decomposed {
+        Decomposed {
+            translation: a.translation + t * (b.translation - a.translation),
+            rotation: Quaternion::slerp(&a.rotation, &b.rotation, t),
+            scale: Mat4::lerp(a.scale, b.scale, t)
+        }
+    }
+}
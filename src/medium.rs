@@ -0,0 +1,277 @@
+use std::f32;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use vec3::*;
+use ray::*;
+use material::*;
+use hitable::*;
+use aabb::*;
+use noise::*;
+
+// Participating media - fog, smoke, fire or anything else that scatters light all through its
+// volume rather than at a surface
+//
+// Density is the chance of a collision per unit distance. Rays that are followed find where
+// they collide, and a ray that gets through without one goes on to whatever is beyond. Shadow
+// rays only need to know how much light gets through, which is estimated without stopping.
+//
+
+// Homogeneous medium, filling the inside of a boundary object
+//
+// The boundary must be convex: a ray is taken to be inside between the first two times it
+// crosses it. The distance to a collision is exponentially distributed.
+//
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hitable>,
+    density: f32,
+    phase: Arc<dyn Material>
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hitable>, density: f32, phase: Arc<dyn Material>) -> ConstantMedium {
+        ConstantMedium { boundary, density, phase }
+    }
+
+    // Where the ray is inside the boundary, within [t_min, t_max]
+    fn range(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        // Where the line enters and leaves the boundary, wherever the ray starts
+        let enter = self.boundary.hit(r, -f32::MAX, f32::MAX)?;
+        let leave = self.boundary.hit(r, enter.t + 1e-4, f32::MAX)?;
+
+        let t0 = enter.t.max(t_min);
+        let t1 = leave.t.min(t_max);
+        if t0 < t1 { Some((t0, t1)) } else { None }
+    }
+}
+
+impl Hitable for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let (t0, t1) = self.range(r, t_min, t_max)?;
+
+        let length = r.direction().length();
+        let distance = -(1.0 - rand()).ln() / self.density;
+        let t = t0 + distance / length;
+        if t >= t1 {
+            return None;
+        }
+
+        // There is no surface, so the normal is arbitrary
+        Some(Hit::new(t, r.point_at_parameter(t), Vec3::new(1.0, 0.0, 0.0), self.phase.as_ref()))
+    }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        self.boundary.axis_aligned_bounding_box()
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32) -> f32 {
+        match self.range(r, t_min, t_max) {
+            Some((t0, t1)) => (-self.density * (t1 - t0) * r.direction().length()).exp(),
+            None => 1.0
+        }
+    }
+}
+
+// Densities on a regular 3D grid of voxels, looked up with trilinear interpolation
+//
+// The grid file format is a text line giving the number of voxels along x, y and z, then that
+// many little endian 32 bit floats, x varying fastest and z slowest.
+//
+pub struct DensityGrid {
+    size: [usize; 3],
+    values: Vec<f32>,
+    max: f32
+}
+
+impl DensityGrid {
+    pub fn new(nx: usize, ny: usize, nz: usize, values: Vec<f32>) -> DensityGrid {
+        assert_eq!(values.len(), nx * ny * nz, "grid size does not match its values");
+        let max = values.iter().cloned().fold(0.0, f32::max);
+        DensityGrid { size: [nx, ny, nz], values, max }
+    }
+
+    pub fn load(path: &Path) -> io::Result<DensityGrid> {
+        let bytes = fs::read(path)?;
+        let bad = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        let newline = bytes.iter().position(|&b| b == b'\n').ok_or_else(|| bad("no grid size line"))?;
+        let header = std::str::from_utf8(&bytes[..newline]).map_err(|_| bad("bad grid size line"))?;
+        let size = header.split_whitespace().map(|n| n.parse::<usize>()).collect::<Result<Vec<_>, _>>()
+            .map_err(|_| bad("bad grid size line"))?;
+        if size.len() != 3 || size.contains(&0) {
+            return Err(bad("grid size should be three positive numbers"));
+        }
+
+        let data = &bytes[newline + 1..];
+        if data.len() != 4 * size[0] * size[1] * size[2] {
+            return Err(bad("grid size does not match the amount of data"));
+        }
+        let values = data.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]).max(0.0)).collect();
+        Ok(DensityGrid::new(size[0], size[1], size[2], values))
+    }
+
+    // A puff of cloud - a noise pattern across the grid, eaten away towards the sphere that
+    // just fits inside it, so that the edge is as ragged as the noise
+    pub fn from_noise(field: &Procedural, resolution: usize) -> DensityGrid {
+        let n = resolution.max(2);
+        let mut values = Vec::with_capacity(n * n * n);
+        for k in 0..n {
+            for j in 0..n {
+                for i in 0..n {
+                    let c = Vec3::new((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32, (k as f32 + 0.5) / n as f32);
+                    let falloff = (1.0 - (2.0 * c - Vec3::new(1.0, 1.0, 1.0)).length_squared()).max(0.0);
+                    values.push((field.value(c) - (1.0 - falloff)).max(0.0));
+                }
+            }
+        }
+        DensityGrid::new(n, n, n, values)
+    }
+
+    pub fn max(&self) -> f32 { self.max }
+
+    fn voxel(&self, i: usize, j: usize, k: usize) -> f32 {
+        self.values[(k * self.size[1] + j) * self.size[0] + i]
+    }
+
+    // Density at c, from 0 to 1 across the grid along each axis
+    pub fn value(&self, c: Vec3) -> f32 {
+        let mut index = [0; 3];
+        let mut frac = [0.0; 3];
+        for a in 0..3 {
+            // Values are at the voxel centres
+            let x = (c[a] * self.size[a] as f32 - 0.5).clamp(0.0, (self.size[a] - 1) as f32);
+            index[a] = (x as usize).min(self.size[a].saturating_sub(2));
+            frac[a] = x - index[a] as f32;
+        }
+
+        let mut total = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut at = index;
+            for a in 0..3 {
+                if corner & (1 << a) != 0 {
+                    weight *= frac[a];
+                    at[a] = (at[a] + 1).min(self.size[a] - 1);
+                } else {
+                    weight *= 1.0 - frac[a];
+                }
+            }
+            if weight > 0.0 {
+                total += weight * self.voxel(at[0], at[1], at[2]);
+            }
+        }
+        total
+    }
+}
+
+// Heterogeneous medium - a density grid stretched over an axis aligned box, optionally glowing
+//
+// Collisions are found by delta tracking: tentative collisions are taken as if the whole box
+// were as dense as its densest voxel, and each is real with the chance of the density there
+// over that. Transmittance is found by ratio tracking, multiplying by the chance of each
+// tentative collision being null instead.
+//
+// The medium is its own material, so that it can glow where it is hit: the emission colour is
+// given off at each collision, scaled by the density there relative to the densest voxel.
+// Scattering is left to the phase material.
+//
+pub struct GridMedium {
+    bounds: Aabb,
+    grid: Arc<DensityGrid>,
+    density: f32,               // Density of a grid value of 1
+    phase: Arc<dyn Material>,
+    emission: Vec3
+}
+
+impl GridMedium {
+    pub fn new(p0: Vec3, p1: Vec3, grid: Arc<DensityGrid>, density: f32, phase: Arc<dyn Material>, emission: Vec3) -> GridMedium {
+        GridMedium { bounds: Aabb::new(p0, p1), grid, density, phase, emission }
+    }
+
+    fn grid_value(&self, p: Vec3) -> f32 {
+        let (lo, hi) = (self.bounds.min(), self.bounds.max());
+        self.grid.value((p - lo) / (hi - lo))
+    }
+
+    // Distance along the ray, in units of t, to the next tentative collision
+    fn step(&self, r: &Ray) -> f32 {
+        -(1.0 - rand()).ln() / (self.density * self.grid.max() * r.direction().length())
+    }
+}
+
+impl Hitable for GridMedium {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        if self.density * self.grid.max() <= 0.0 {
+            return None;
+        }
+        let (t0, t1) = self.bounds.range(r, t_min, t_max)?;
+
+        let mut t = t0;
+        loop {
+            t += self.step(r);
+            if t >= t1 {
+                return None;
+            }
+            let p = r.point_at_parameter(t);
+            if rand() * self.grid.max() < self.grid_value(p) {
+                return Some(Hit::new(t, p, Vec3::new(1.0, 0.0, 0.0), self));
+            }
+        }
+    }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        Some(self.bounds)
+    }
+
+    fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32) -> f32 {
+        if self.density * self.grid.max() <= 0.0 {
+            return 1.0;
+        }
+        let (t0, t1) = match self.bounds.range(r, t_min, t_max) {
+            Some(range) => range,
+            None => return 1.0
+        };
+
+        let mut transmittance = 1.0;
+        let mut t = t0;
+        loop {
+            t += self.step(r);
+            if t >= t1 {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.grid_value(r.point_at_parameter(t)) / self.grid.max();
+
+            // Russian roulette once little is getting through, keeping the estimate unbiased
+            if transmittance < 0.1 {
+                if rand() < 0.5 {
+                    return 0.0;
+                }
+                transmittance *= 2.0;
+            }
+        }
+    }
+}
+
+impl Material for GridMedium {
+    fn scatter(&self, r_in: &Ray, rec: &Hit) -> Option<Scattered> {
+        self.phase.scatter(r_in, rec)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> Vec3 {
+        self.phase.eval(r_in, rec, wi)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> f32 {
+        self.phase.pdf(r_in, rec, wi)
+    }
+
+    fn to_spectrum(&self, r_in: &Ray, rec: &Hit, wi: Vec3, value: Vec3, lambdas: [f32; 3]) -> Vec3 {
+        self.phase.to_spectrum(r_in, rec, wi, value, lambdas)
+    }
+
+    fn emitted(&self, _r_in: &Ray, rec: &Hit) -> Vec3 {
+        self.emission * (self.grid_value(rec.p) / self.grid.max())
+    }
+}
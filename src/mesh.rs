@@ -0,0 +1,216 @@
+use std::f32;
+use std::sync::Arc;
+
+use vec3::*;
+use ray::*;
+use material::*;
+use hitable::*;
+use aabb::*;
+use bvh::*;
+
+// Möller–Trumbore ray/triangle intersection - returns (t, b1, b2), where b1 and b2
+// are the barycentric weights of p1 and p2
+//
+fn intersect_triangle(r: &Ray, p0: Vec3, p1: Vec3, p2: Vec3, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+    let e1 = p1 - p0;
+    let e2 = p2 - p0;
+    let pvec = cross(r.direction(), e2);
+    let det = dot(e1, pvec);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = r.origin() - p0;
+    let b1 = dot(tvec, pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = cross(tvec, e1);
+    let b2 = dot(r.direction(), qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = dot(e2, qvec) * inv_det;
+    if t < t_max && t > t_min {
+        Some((t, b1, b2))
+    } else {
+        None
+    }
+}
+
+// Uniformly distributed point on a triangle
+//
+fn sample_triangle(p0: Vec3, p1: Vec3, p2: Vec3) -> Vec3 {
+    let su = rand().sqrt();
+    let r2 = rand();
+    (1.0 - su) * p0 + (su * (1.0 - r2)) * p1 + (su * r2) * p2
+}
+
+fn triangle_area(p0: Vec3, p1: Vec3, p2: Vec3) -> f32 {
+    0.5 * cross(p1 - p0, p2 - p0).length()
+}
+
+// A ray footprint's width in texture space, from the triangle's areas in space and in (u, v)
+fn uv_width(footprint: f32, area: f32, uv_area: f32) -> f32 {
+    if area > 0.0 { footprint * (uv_area / area).sqrt() } else { 0.0 }
+}
+
+// Single triangle, with a flat normal
+//
+pub struct Triangle {
+    p: [Vec3; 3],
+    material: Arc<dyn Material>
+}
+
+impl Triangle {
+    pub fn new(p0: Vec3, p1: Vec3, p2: Vec3, m: Arc<dyn Material>) -> Triangle { Triangle { p:[p0, p1, p2], material:m } }
+}
+
+impl Hitable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let (t, b1, b2) = intersect_triangle(r, self.p[0], self.p[1], self.p[2], t_min, t_max)?;
+        let n = unit_vector(cross(self.p[1] - self.p[0], self.p[2] - self.p[0]));
+        let uv_width = uv_width(r.footprint(t), triangle_area(self.p[0], self.p[1], self.p[2]), 0.5);
+        Some(Hit { t, p: r.point_at_parameter(t), n, ng: n, u: b1, v: b2, uv_width, m: self.material.as_ref() })
+    }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        Some(surrounding_box(Aabb::new(self.p[0], self.p[1]), Aabb::new(self.p[2], self.p[2])))
+    }
+
+    fn is_emissive(&self) -> bool { self.material.is_emissive() }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f32 {
+        match self.hit(&Ray::new(o, v), 0.001, f32::MAX) {
+            Some(rec) => area_pdf(v, &rec, triangle_area(self.p[0], self.p[1], self.p[2])),
+            None => 0.0
+        }
+    }
+
+    fn random(&self, o: Vec3) -> Vec3 {
+        sample_triangle(self.p[0], self.p[1], self.p[2]) - o
+    }
+}
+
+// Vertex and index buffers shared by all the triangles of a mesh
+//
+struct MeshData {
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<(f32, f32)>>,
+    indices: Vec<[usize; 3]>,
+    material: Arc<dyn Material>
+}
+
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    index: usize
+}
+
+impl Hitable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let mesh = &*self.mesh;
+        let [i0, i1, i2] = mesh.indices[self.index];
+        let (p0, p1, p2) = (mesh.positions[i0], mesh.positions[i1], mesh.positions[i2]);
+
+        let (t, b1, b2) = intersect_triangle(r, p0, p1, p2, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+
+        let mut ng = unit_vector(cross(p1 - p0, p2 - p0));
+        let n = match mesh.normals {
+            Some(ref normals) => {
+                let ns = unit_vector(b0 * normals[i0] + b1 * normals[i1] + b2 * normals[i2]);
+                // Keep the geometric normal on the same side as the interpolated one
+                if dot(ng, ns) < 0.0 {
+                    ng = -ng;
+                }
+                ns
+            }
+            None => ng
+        };
+
+        let (u, v, uv_area) = match mesh.uvs {
+            Some(ref uvs) => {
+                let e1 = (uvs[i1].0 - uvs[i0].0, uvs[i1].1 - uvs[i0].1);
+                let e2 = (uvs[i2].0 - uvs[i0].0, uvs[i2].1 - uvs[i0].1);
+                (b0 * uvs[i0].0 + b1 * uvs[i1].0 + b2 * uvs[i2].0,
+                 b0 * uvs[i0].1 + b1 * uvs[i1].1 + b2 * uvs[i2].1,
+                 0.5 * (e1.0 * e2.1 - e1.1 * e2.0).abs())
+            }
+            None => (b1, b2, 0.5)
+        };
+        let uv_width = uv_width(r.footprint(t), triangle_area(p0, p1, p2), uv_area);
+
+        Some(Hit { t, p: r.point_at_parameter(t), n, ng, u, v, uv_width, m: mesh.material.as_ref() })
+    }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        let [i0, i1, i2] = self.mesh.indices[self.index];
+        let p = &self.mesh.positions;
+        Some(surrounding_box(Aabb::new(p[i0], p[i1]), Aabb::new(p[i2], p[i2])))
+    }
+}
+
+// Indexed triangle mesh, with optional per-vertex normals and UVs. The triangles are
+// held in their own BVH.
+//
+pub struct TriangleMesh {
+    bvh: BvhNode,
+    mesh: Arc<MeshData>,
+    cdf: Vec<f32>           // Running total of triangle areas, for picking one to sample
+}
+
+impl TriangleMesh {
+    pub fn new(positions: Vec<Vec3>, indices: Vec<[usize; 3]>, normals: Option<Vec<Vec3>>, uvs: Option<Vec<(f32, f32)>>, m: Arc<dyn Material>) -> TriangleMesh {
+        assert!(indices.iter().all(|tri| tri.iter().all(|&i| i < positions.len())), "mesh index out of range");
+        assert!(normals.as_ref().is_none_or(|n| n.len() == positions.len()), "mesh normal count differs from positions");
+        assert!(uvs.as_ref().is_none_or(|uv| uv.len() == positions.len()), "mesh UV count differs from positions");
+
+        let count = indices.len();
+        let mesh = Arc::new(MeshData { positions, normals, uvs, indices, material: m });
+
+        let triangles = (0..count).map(|index| Box::new(MeshTriangle { mesh: mesh.clone(), index }) as Box<dyn Hitable>).collect();
+
+        let mut total = 0.0;
+        let cdf = mesh.indices.iter().map(|&[i0, i1, i2]| {
+            total += triangle_area(mesh.positions[i0], mesh.positions[i1], mesh.positions[i2]);
+            total
+        }).collect();
+
+        TriangleMesh { bvh: BvhNode::new(triangles), mesh, cdf }
+    }
+
+    fn area(&self) -> f32 {
+        self.cdf.last().cloned().unwrap_or(0.0)
+    }
+}
+
+impl Hitable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        self.bvh.hit(r, t_min, t_max)
+    }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        self.bvh.axis_aligned_bounding_box()
+    }
+
+    fn is_emissive(&self) -> bool { self.mesh.material.is_emissive() && self.area() > 0.0 }
+
+    // Uniform over the whole surface
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f32 {
+        match self.hit(&Ray::new(o, v), 0.001, f32::MAX) {
+            Some(rec) => area_pdf(v, &rec, self.area()),
+            None => 0.0
+        }
+    }
+
+    fn random(&self, o: Vec3) -> Vec3 {
+        let a = rand() * self.area();
+        let t = self.cdf.partition_point(|&c| c < a).min(self.cdf.len() - 1);
+        let [i0, i1, i2] = self.mesh.indices[t];
+        sample_triangle(self.mesh.positions[i0], self.mesh.positions[i1], self.mesh.positions[i2]) - o
+    }
+}
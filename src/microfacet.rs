@@ -0,0 +1,413 @@
+use std::f32::consts::PI;
+
+use vec3::*;
+use ray::*;
+use material::*;
+use spectrum::*;
+
+// Microfacet materials
+//
+// Rough surfaces are modelled as tiny mirror facets, whose normals follow the GGX (Trowbridge-
+// Reitz) distribution, shadowing and masking each other as Smith's height correlated function
+// says (Walter et al. 2007, Heitz 2014). Only the facets the incoming ray can see are sampled
+// (Heitz 2018), so few samples are wasted at grazing angles.
+//
+// Roughness is perceptual, from 0 (a mirror) to 1: the distribution's alpha is its square.
+// Light that would bounce between facets more than once is lost, so very rough surfaces come
+// out a little dark.
+//
+
+// Below this alpha a surface is treated as perfectly smooth
+const SMOOTH: f32 = 1e-3;
+
+// Directions relative to a surface, with the normal along z
+//
+pub struct Frame {
+    s: Vec3,
+    t: Vec3,
+    n: Vec3
+}
+
+impl Frame {
+    pub fn new(n: Vec3) -> Frame {
+        let (s, t) = orthonormal_basis(n);
+        Frame { s, t, n }
+    }
+
+    pub fn to_local(&self, v: Vec3) -> Vec3 { Vec3::new(dot(v, self.s), dot(v, self.t), dot(v, self.n)) }
+    pub fn to_world(&self, v: Vec3) -> Vec3 { v.x() * self.s + v.y() * self.t + v.z() * self.n }
+}
+
+// Fresnel reflectance of a dielectric, for light arriving at cos_i to the normal from the side
+// whose index is 1, where the other side's is eta. A negative cos_i is from the other side.
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let (cos_i, eta) = if cos_i < 0.0 { (-cos_i, 1.0 / eta) } else { (cos_i, eta) };
+    let cos_i = cos_i.min(1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// Fresnel reflectance of a conductor of complex index eta + ik, for unpolarised light
+pub fn fresnel_conductor(cos_i: f32, eta: f32, k: f32) -> f32 {
+    let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos_i.clamp(0.0, 1.0) * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rs + rp)
+}
+
+// The GGX distribution of facet normals, in the local frame
+//
+#[derive(Copy, Clone)]
+pub struct Ggx {
+    alpha: f32
+}
+
+impl Ggx {
+    pub fn new(roughness: f32) -> Ggx { Ggx { alpha: (roughness * roughness).max(0.0) } }
+
+    pub fn is_smooth(&self) -> bool { self.alpha < SMOOTH }
+
+    // Density of facet normal m, per unit projected area
+    pub fn d(&self, m: Vec3) -> f32 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let c = m.z() * m.z() * (a2 - 1.0) + 1.0;
+        a2 / (PI * c * c)
+    }
+
+    fn lambda(&self, w: Vec3) -> f32 {
+        let cos2 = w.z() * w.z();
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    // Fraction of facets seen from w that are not masked
+    pub fn g1(&self, w: Vec3) -> f32 { 1.0 / (1.0 + self.lambda(w)) }
+
+    // Fraction seen from both wo and wi
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f32 { 1.0 / (1.0 + self.lambda(wo) + self.lambda(wi)) }
+
+    // A facet normal seen from wo (above the surface), in proportion to its projected area
+    pub fn sample_visible(&self, wo: Vec3) -> Vec3 {
+        // Stretch so the distribution becomes a hemisphere, and sample its projection
+        let vh = unit_vector(Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()));
+        let lensq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if lensq > 0.0 { Vec3::new(-vh.y(), vh.x(), 0.0) / lensq.sqrt() } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2 = cross(vh, t1);
+
+        let r = rand().sqrt();
+        let phi = 2.0 * PI * rand();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        unit_vector(Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(1e-6)))
+    }
+
+    // Density with which sample_visible() picks m
+    pub fn pdf_visible(&self, wo: Vec3, m: Vec3) -> f32 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * dot(wo, m).max(0.0) * self.d(m) / wo.z()
+    }
+
+    // Reflection from wo into wi through the facet between them: the facet, the BRDF * cosine
+    // leaving out Fresnel, and the density of picking wi by reflecting about sample_visible()
+    pub fn reflection(&self, wo: Vec3, wi: Vec3) -> Option<(Vec3, f32, f32)> {
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return None;
+        }
+        let m = unit_vector(wo + wi);
+        Some((m, self.d(m) * self.g2(wo, wi) / (4.0 * wo.z()), self.pdf_visible(wo, m) / (4.0 * dot(wo, m))))
+    }
+
+    // Refraction from wo into wi below the surface, where the index there relative to wo's side
+    // is eta: the facet, the BTDF * cosine leaving out Fresnel, and the density of picking wi by
+    // refracting through sample_visible()
+    pub fn refraction(&self, wo: Vec3, wi: Vec3, eta: f32) -> Option<(Vec3, f32, f32)> {
+        if wo.z() <= 0.0 || wi.z() >= 0.0 {
+            return None;
+        }
+        let m = wo + eta * wi;
+        if m.length_squared() == 0.0 {
+            return None;
+        }
+        let m = unit_vector(m);
+        let m = if m.z() < 0.0 { -m } else { m };
+        // Facets seen from behind can not do it
+        if dot(wo, m) <= 0.0 || dot(wi, m) >= 0.0 {
+            return None;
+        }
+
+        // How much the facet normal turns as wi does, through the refraction
+        let denom = dot(wi, m) + dot(wo, m) / eta;
+        let dm_dwi = -dot(wi, m) / (denom * denom);
+        // Radiance is scaled by the square of the ratio of the indices on crossing
+        let value = self.d(m) * self.g2(wo, wi) * dot(wo, m) * dm_dwi / (wo.z() * eta * eta);
+        Some((m, value, self.pdf_visible(wo, m) * dm_dwi))
+    }
+}
+
+// Mirror wo about m - both pointing away from the surface
+fn reflect_about(wo: Vec3, m: Vec3) -> Vec3 {
+    reflect(-wo, m)
+}
+
+// Conductor - a metal described by its complex index of refraction, so its colour, and the
+// way that turns white at grazing angles, both follow from measured optical constants
+//
+pub struct Conductor {
+    eta: Vec3,
+    k: Vec3,
+    spectrum: Option<&'static [(f32, f32, f32)]>,
+    distribution: Ggx
+}
+
+// Measured optical constants - wavelength in nanometres, eta and k - for spectral rendering
+// (Johnson and Christy 1972 for the noble metals, Rakic 1995 for aluminium)
+const GOLD: [(f32, f32, f32); 7] = [
+    (400.0, 1.658, 1.956), (450.0, 1.380, 1.916), (500.0, 0.970, 1.870), (550.0, 0.430, 2.455),
+    (600.0, 0.250, 2.980), (650.0, 0.166, 3.500), (700.0, 0.131, 4.000)
+];
+const SILVER: [(f32, f32, f32); 7] = [
+    (400.0, 0.173, 1.950), (450.0, 0.144, 2.450), (500.0, 0.130, 2.920), (550.0, 0.120, 3.350),
+    (600.0, 0.121, 3.800), (650.0, 0.140, 4.200), (700.0, 0.150, 4.700)
+];
+const COPPER: [(f32, f32, f32); 7] = [
+    (400.0, 1.180, 2.210), (450.0, 1.170, 2.390), (500.0, 1.130, 2.560), (550.0, 0.940, 2.580),
+    (600.0, 0.250, 3.420), (650.0, 0.210, 3.670), (700.0, 0.210, 4.050)
+];
+const ALUMINIUM: [(f32, f32, f32); 7] = [
+    (400.0, 0.420, 4.450), (450.0, 0.550, 5.000), (500.0, 0.700, 5.600), (550.0, 0.900, 6.300),
+    (600.0, 1.150, 6.950), (650.0, 1.450, 7.600), (700.0, 1.800, 8.300)
+];
+
+// Linear interpolation in a table of optical constants, held at the ends
+fn optical_constants(table: &[(f32, f32, f32)], lambda: f32) -> (f32, f32) {
+    let i = table.iter().position(|e| e.0 > lambda).unwrap_or(table.len());
+    if i == 0 {
+        return (table[0].1, table[0].2);
+    }
+    if i == table.len() {
+        return (table[i - 1].1, table[i - 1].2);
+    }
+    let (a, b) = (table[i - 1], table[i]);
+    let t = (lambda - a.0) / (b.0 - a.0);
+    (a.1 + t * (b.1 - a.1), a.2 + t * (b.2 - a.2))
+}
+
+impl Conductor {
+    pub fn new(eta: Vec3, k: Vec3, roughness: f32) -> Conductor { Conductor { eta, k, spectrum: None, distribution: Ggx::new(roughness) } }
+
+    // Optical constants at the middle of red, green and blue, and measured across the spectrum
+    // for the metals there is a table for
+    pub fn named(name: &str, roughness: f32) -> Option<Conductor> {
+        let (eta, k) = match name {
+            "gold" => (Vec3::new(0.143, 0.374, 1.442), Vec3::new(3.983, 2.385, 1.603)),
+            "silver" => (Vec3::new(0.155, 0.117, 0.138), Vec3::new(4.828, 3.122, 2.147)),
+            "copper" => (Vec3::new(0.200, 0.924, 1.102), Vec3::new(3.912, 2.452, 2.142)),
+            "aluminium" => (Vec3::new(1.657, 0.880, 0.521), Vec3::new(9.224, 6.270, 4.837)),
+            "iron" => (Vec3::new(2.911, 2.950, 2.585), Vec3::new(3.089, 2.932, 2.767)),
+            "chrome" => (Vec3::new(3.107, 3.182, 2.228), Vec3::new(3.315, 3.332, 3.060)),
+            _ => return None
+        };
+        let spectrum: Option<&'static [(f32, f32, f32)]> = match name {
+            "gold" => Some(&GOLD),
+            "silver" => Some(&SILVER),
+            "copper" => Some(&COPPER),
+            "aluminium" => Some(&ALUMINIUM),
+            _ => None
+        };
+        Some(Conductor { spectrum, ..Conductor::new(eta, k, roughness) })
+    }
+
+    fn fresnel(&self, cos_i: f32) -> Vec3 {
+        Vec3::new(fresnel_conductor(cos_i, self.eta.x(), self.k.x()),
+                  fresnel_conductor(cos_i, self.eta.y(), self.k.y()),
+                  fresnel_conductor(cos_i, self.eta.z(), self.k.z()))
+    }
+
+    // The frame of the side the ray arrived on, and the direction back along the ray in it
+    fn local(&self, r_in: &Ray, rec: &Hit) -> (Frame, Vec3) {
+        let frame = Frame::new(rec.facing_normal(r_in));
+        let wo = frame.to_local(-unit_vector(r_in.direction()));
+        (frame, wo)
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, r_in: &Ray, rec: &Hit) -> Option<Scattered> {
+        let (frame, wo) = self.local(r_in, rec);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        if self.distribution.is_smooth() {
+            let wi = Vec3::new(-wo.x(), -wo.y(), wo.z());
+            return Some(Scattered { scattered: Ray::new(rec.p, frame.to_world(wi)), attenuation: self.fresnel(wo.z()), pdf: 0.0 });
+        }
+
+        let m = self.distribution.sample_visible(wo);
+        let wi = reflect_about(wo, m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+
+        // BRDF * cosine / pdf, with most of it cancelling
+        let g = self.distribution.g2(wo, wi) / self.distribution.g1(wo);
+        let pdf = self.distribution.pdf_visible(wo, m) / (4.0 * dot(wo, m));
+        Some(Scattered { scattered: Ray::new(rec.p, frame.to_world(wi)), attenuation: self.fresnel(dot(wo, m)) * g, pdf })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> Vec3 {
+        let (frame, wo) = self.local(r_in, rec);
+        if self.distribution.is_smooth() {
+            return Vec3::zero();
+        }
+        match self.distribution.reflection(wo, frame.to_local(unit_vector(wi))) {
+            Some((m, value, _)) => self.fresnel(dot(wo, m)) * value,
+            None => Vec3::zero()
+        }
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> f32 {
+        let (frame, wo) = self.local(r_in, rec);
+        if self.distribution.is_smooth() {
+            return 0.0;
+        }
+        match self.distribution.reflection(wo, frame.to_local(unit_vector(wi))) {
+            Some((_, _, pdf)) => pdf,
+            None => 0.0
+        }
+    }
+
+    // Everything but the Fresnel term is the same in each channel, so swap the RGB Fresnel for
+    // the measured one at each wavelength
+    fn to_spectrum(&self, r_in: &Ray, rec: &Hit, wi: Vec3, value: Vec3, lambdas: [f32; 3]) -> Vec3 {
+        let table = match self.spectrum {
+            Some(table) => table,
+            None => return rgb_to_spectrum(value, lambdas)
+        };
+        let (frame, wo) = self.local(r_in, rec);
+        let h = wo + frame.to_local(unit_vector(wi));
+        if h.length_squared() < 1e-12 {
+            return Vec3::zero();
+        }
+        let cos_i = dot(wo, unit_vector(h));
+        let rest = (value / self.fresnel(cos_i)).g();
+        let fresnel = |lambda: f32| {
+            let (eta, k) = optical_constants(table, lambda);
+            fresnel_conductor(cos_i, eta, k)
+        };
+        Vec3::new(fresnel(lambdas[0]), fresnel(lambdas[1]), fresnel(lambdas[2])) * rest
+    }
+}
+
+// Rough dielectric - frosted glass, reflecting and refracting through GGX facets (Walter et al.
+// 2007). Each facet reflects or refracts in proportion to its Fresnel reflectance.
+//
+pub struct RoughDielectric {
+    ior: f32,
+    distribution: Ggx
+}
+
+impl RoughDielectric {
+    pub fn new(ior: f32, roughness: f32) -> RoughDielectric { RoughDielectric { ior, distribution: Ggx::new(roughness) } }
+
+    // The frame of the side the ray arrived on, the direction back along the ray in it, and
+    // the index of the far side relative to this one
+    fn local(&self, r_in: &Ray, rec: &Hit) -> (Frame, Vec3, f32) {
+        let entering = dot(r_in.direction(), rec.n) < 0.0;
+        let (n, eta) = if entering { (rec.n, self.ior) } else { (-rec.n, 1.0 / self.ior) };
+        let frame = Frame::new(n);
+        let wo = frame.to_local(-unit_vector(r_in.direction()));
+        (frame, wo, eta)
+    }
+
+    // BSDF * cosine and the density of sampling wi, both in the local frame
+    fn eval_pdf(&self, wo: Vec3, wi: Vec3, eta: f32) -> (f32, f32) {
+        if let Some((m, value, pdf)) = self.distribution.reflection(wo, wi) {
+            let f = fresnel_dielectric(dot(wo, m), eta);
+            (f * value, f * pdf)
+        } else if let Some((m, value, pdf)) = self.distribution.refraction(wo, wi, eta) {
+            let t = 1.0 - fresnel_dielectric(dot(wo, m), eta);
+            (t * value, t * pdf)
+        } else {
+            (0.0, 0.0)
+        }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, r_in: &Ray, rec: &Hit) -> Option<Scattered> {
+        let (frame, wo, eta) = self.local(r_in, rec);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let smooth = self.distribution.is_smooth();
+        let m = if smooth { Vec3::new(0.0, 0.0, 1.0) } else { self.distribution.sample_visible(wo) };
+        let f = fresnel_dielectric(dot(wo, m), eta);
+
+        // A facet can send the ray to the wrong side of the surface as a whole, which is lost
+        let wi = if rand() < f {
+            Some(reflect_about(wo, m)).filter(|wi| wi.z() > 0.0)?
+        } else {
+            refract(-wo, m, 1.0 / eta).filter(|wi| wi.z() < 0.0)?
+        };
+
+        if smooth {
+            // The choice between the two is in proportion to their weights, so they cancel;
+            // radiance is still scaled by the change in index
+            let attenuation = if wi.z() < 0.0 { 1.0 / (eta * eta) } else { 1.0 };
+            return Some(Scattered { scattered: Ray::new(rec.p, frame.to_world(wi)), attenuation: Vec3::new(attenuation, attenuation, attenuation), pdf: 0.0 });
+        }
+
+        let (value, pdf) = self.eval_pdf(wo, wi, eta);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let weight = value / pdf;
+        Some(Scattered { scattered: Ray::new(rec.p, frame.to_world(wi)), attenuation: Vec3::new(weight, weight, weight), pdf })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> Vec3 {
+        let (frame, wo, eta) = self.local(r_in, rec);
+        if self.distribution.is_smooth() || wo.z() <= 0.0 {
+            return Vec3::zero();
+        }
+        let value = self.eval_pdf(wo, frame.to_local(unit_vector(wi)), eta).0;
+        Vec3::new(value, value, value)
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> f32 {
+        let (frame, wo, eta) = self.local(r_in, rec);
+        if self.distribution.is_smooth() || wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.eval_pdf(wo, frame.to_local(unit_vector(wi)), eta).1
+    }
+}
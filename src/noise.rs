@@ -0,0 +1,233 @@
+use std::f32::consts::PI;
+
+use vec3::*;
+
+// Procedural noise
+//
+// Everything here is a pure function of the point and a seed, so a scene looks the same
+// whichever thread renders it, and the same seed always gives the same pattern.
+//
+
+// Hash of a lattice cell, for picking its random features
+fn hash_cell(seed: u64, i: i32, j: i32, k: i32) -> u64 {
+    let mut state = seed ^
+        (i as u32 as u64).wrapping_mul(0x8cb9_2ba7_2f3d_8dd7) ^
+        (j as u32 as u64).wrapping_mul(0xd6e8_feb8_6659_fd93) ^
+        (k as u32 as u64).wrapping_mul(0xa076_1d64_78bd_642f);
+    splitmix64(&mut state)
+}
+
+fn to_unit(bits: u64) -> f32 {
+    (bits >> 40) as f32 / (1u64 << 24) as f32
+}
+
+// Improved Perlin noise (Perlin 2002)
+//
+// Gradients are picked from the twelve cube edge directions through a shuffled permutation
+// table, and blended with the quintic fade curve, which has no second derivative jumps at
+// the lattice - so no creases when used for bump mapping.
+//
+
+pub struct Perlin {
+    perm: Vec<usize>        // Permutation of 0..256, repeated so indices can run past 255
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+fn grad(hash: usize, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 { y } else if h == 12 || h == 14 { x } else { z };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut state = seed;
+        let mut p: Vec<usize> = (0..256).collect();
+        for i in (1..256).rev() {
+            let target = (splitmix64(&mut state) % (i as u64 + 1)) as usize;
+            p.swap(i, target);
+        }
+        let perm = p.iter().chain(p.iter()).cloned().collect();
+        Perlin { perm }
+    }
+
+    // Roughly in [-1, 1], and zero at every lattice point
+    pub fn noise(&self, p: Vec3) -> f32 {
+        let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (x, y, z) = (p.x() - fx, p.y() - fy, p.z() - fz);
+        let (i, j, k) = ((fx as i32 & 255) as usize, (fy as i32 & 255) as usize, (fz as i32 & 255) as usize);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+
+        let perm = &self.perm;
+        let a = perm[i] + j;
+        let (aa, ab) = (perm[a] + k, perm[a + 1] + k);
+        let b = perm[i + 1] + j;
+        let (ba, bb) = (perm[b] + k, perm[b + 1] + k);
+
+        lerp(w, lerp(v, lerp(u, grad(perm[aa], x, y, z),
+                                grad(perm[ba], x - 1.0, y, z)),
+                        lerp(u, grad(perm[ab], x, y - 1.0, z),
+                                grad(perm[bb], x - 1.0, y - 1.0, z))),
+                lerp(v, lerp(u, grad(perm[aa + 1], x, y, z - 1.0),
+                                grad(perm[ba + 1], x - 1.0, y, z - 1.0)),
+                        lerp(u, grad(perm[ab + 1], x, y - 1.0, z - 1.0),
+                                grad(perm[bb + 1], x - 1.0, y - 1.0, z - 1.0))))
+    }
+
+    // Fractional Brownian motion - octaves of noise, each at double the frequency and half the weight
+    pub fn fbm(&self, p: Vec3, octaves: usize) -> f32 {
+        let mut accum = 0.0;
+        let mut temp_p = p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            accum += weight * self.noise(temp_p);
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+        accum
+    }
+
+    // Like fBm, but summing the absolute value of each octave, which gives creases where
+    // the noise crosses zero
+    pub fn turbulence(&self, p: Vec3, octaves: usize) -> f32 {
+        let mut accum = 0.0;
+        let mut temp_p = p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            accum += weight * self.noise(temp_p).abs();
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+        accum
+    }
+}
+
+// Worley (cellular) noise
+//
+// One feature point at a random place in each unit cell. The distances from p to the nearest
+// and second nearest of them give cell-like patterns.
+//
+
+pub struct Worley {
+    seed: u64
+}
+
+impl Worley {
+    pub fn new(seed: u64) -> Worley { Worley { seed } }
+
+    // Distances to the nearest and second nearest feature points (F1, F2)
+    pub fn distances(&self, p: Vec3) -> (f32, f32) {
+        let (i, j, k) = (p.x().floor() as i32, p.y().floor() as i32, p.z().floor() as i32);
+        let mut f1 = f32::MAX;
+        let mut f2 = f32::MAX;
+
+        // Feature points are within their cells, so only the neighbouring cells can be nearer
+        for di in -1..2 {
+            for dj in -1..2 {
+                for dk in -1..2 {
+                    let (ci, cj, ck) = (i + di, j + dj, k + dk);
+                    let mut state = hash_cell(self.seed, ci, cj, ck);
+                    let feature = Vec3::new(ci as f32 + to_unit(splitmix64(&mut state)),
+                                            cj as f32 + to_unit(splitmix64(&mut state)),
+                                            ck as f32 + to_unit(splitmix64(&mut state)));
+                    let d = (feature - p).length();
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
+
+// Patterns built from the noise functions, each giving a value in [0, 1]
+//
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Pattern {
+    Perlin,
+    Fbm,
+    Turbulence,
+    Worley,
+    Marble,     // Sine bands along z, distorted by turbulence
+    Wood,       // Rings around the y axis, with noisy grain
+    Stone       // Worley cells separated by dark cracks, mottled with fBm
+}
+
+impl Pattern {
+    pub fn from_name(name: &str) -> Option<Pattern> {
+        match name {
+            "perlin" => Some(Pattern::Perlin),
+            "fbm" => Some(Pattern::Fbm),
+            "turbulence" => Some(Pattern::Turbulence),
+            "worley" => Some(Pattern::Worley),
+            "marble" => Some(Pattern::Marble),
+            "wood" => Some(Pattern::Wood),
+            "stone" => Some(Pattern::Stone),
+            _ => None
+        }
+    }
+}
+
+const OCTAVES: usize = 7;
+
+// A pattern at a given scale - usable as a texture or a height field for bump mapping
+//
+pub struct Procedural {
+    pattern: Pattern,
+    scale: f32,
+    perlin: Perlin,
+    worley: Worley
+}
+
+impl Procedural {
+    pub fn new(pattern: Pattern, scale: f32, seed: u64) -> Procedural {
+        let mut state = seed;
+        Procedural {
+            pattern, scale,
+            perlin: Perlin::new(splitmix64(&mut state)),
+            worley: Worley::new(splitmix64(&mut state))
+        }
+    }
+
+    pub fn value(&self, p: Vec3) -> f32 {
+        let p = self.scale * p;
+        let v = match self.pattern {
+            Pattern::Perlin => 0.5 * (1.0 + self.perlin.noise(p)),
+            Pattern::Fbm => 0.5 * (1.0 + self.perlin.fbm(p, OCTAVES)),
+            Pattern::Turbulence => self.perlin.turbulence(p, OCTAVES),
+            Pattern::Worley => self.worley.distances(p).0,
+            Pattern::Marble => 0.5 * (1.0 + (p.z() + 10.0 * self.perlin.turbulence(0.5 * p, OCTAVES)).sin()),
+            Pattern::Wood => {
+                let r = (p.x() * p.x() + p.z() * p.z()).sqrt();
+                let rings = 4.0 * r + 1.5 * self.perlin.fbm(Vec3::new(p.x(), 0.1 * p.y(), p.z()), 3);
+                let grain = 0.15 * self.perlin.noise(Vec3::new(20.0 * p.x(), 0.5 * p.y(), 20.0 * p.z()));
+                0.5 * (1.0 - (2.0 * PI * rings).cos()) * 0.85 + grain
+            }
+            Pattern::Stone => {
+                let (f1, f2) = self.worley.distances(p);
+                let crack = (6.0 * (f2 - f1)).min(1.0);
+                crack * (0.55 + 0.45 * self.perlin.fbm(4.0 * p, 4))
+            }
+        };
+        v.clamp(0.0, 1.0)
+    }
+
+    // Slope of value() in space, by central differences
+    pub fn gradient(&self, p: Vec3) -> Vec3 {
+        let e = 1e-3 / self.scale.abs().max(1e-6);
+        let d = |o: Vec3| self.value(p + o) - self.value(p - o);
+        Vec3::new(d(Vec3::new(e, 0.0, 0.0)), d(Vec3::new(0.0, e, 0.0)), d(Vec3::new(0.0, 0.0, e))) / (2.0 * e)
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use vec3::*;
+use material::*;
+use principled::*;
+use texture::*;
+use hitable::*;
+use mesh::*;
+use error::*;
+use statement::*;
+
+// Wavefront OBJ + MTL import
+//
+// Each material used by an OBJ file becomes one TriangleMesh. Polygons are triangulated as fans.
+// MTL parameters are mapped onto the closest of the existing materials:
+//   emissive (Ke non-zero)                           -> DiffuseLight, using Ke
+//   PBR extensions (any of Pr, Pm, Ps, Pc, Pcr)      -> Principled, using map_Kd or Kd, and 1 - d as transmission
+//   transparent (d < 1, or a refracting illum model) -> Dielectric, using Ni
+//   specular dominant (Ks brighter than Kd)          -> Metal, with fuzz derived from Ns
+//   otherwise                                        -> Lambertian, using map_Kd or else Kd
+//
+
+#[derive(Clone)]
+struct MtlMaterial {
+    kd: Vec3,
+    ks: Vec3,
+    ns: f32,
+    ni: f32,
+    d: f32,
+    ke: Vec3,
+    illum: u32,
+    map_kd: Option<Arc<dyn Texture>>,
+    pbr: Option<PrincipledParameters>
+}
+
+impl MtlMaterial {
+    fn new() -> MtlMaterial {
+        MtlMaterial { kd: Vec3::new(0.8, 0.8, 0.8), ks: Vec3::zero(), ns: 0.0, ni: 1.5, d: 1.0, ke: Vec3::zero(), illum: 2, map_kd: None, pbr: None }
+    }
+
+    fn base_colour(&self) -> Arc<dyn Texture> {
+        match self.map_kd {
+            Some(ref map) => map.clone(),
+            None => Arc::new(SolidColour::new(self.kd))
+        }
+    }
+
+    fn to_material(&self) -> Arc<dyn Material> {
+        let max = |c: Vec3| c.r().max(c.g()).max(c.b());
+
+        if max(self.ke) > 0.0 {
+            Arc::new(DiffuseLight::new(self.ke))
+        } else if let Some(p) = self.pbr {
+            Arc::new(Principled::new(self.base_colour(), PrincipledParameters { transmission: 1.0 - self.d, ..p }))
+        } else if self.d < 1.0 || [4, 6, 7, 9].contains(&self.illum) {
+            Arc::new(Dielectric::new(self.ni))
+        } else if max(self.ks) > 0.0 && (self.illum == 3 || max(self.ks) > max(self.kd)) {
+            // Blinn-Phong exponent to an equivalent roughness
+            let fuzz = (2.0 / (self.ns + 2.0)).sqrt().min(1.0);
+            Arc::new(Metal::new(self.ks, fuzz))
+        } else {
+            Arc::new(Lambertian::textured(self.base_colour()))
+        }
+    }
+}
+
+fn load_mtl(path: &Path, materials: &mut HashMap<String, MtlMaterial>) -> Result<(), LoadError> {
+    let text = fs::read_to_string(path).map_err(|e| LoadError::io(path, e))?;
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for mut s in statements(path, &text) {
+        if s.keyword == "newmtl" {
+            if let Some((name, m)) = current.take() {
+                materials.insert(name, m);
+            }
+            current = Some((s.name()?.to_string(), MtlMaterial::new()));
+            continue;
+        }
+
+        let m = match current {
+            Some((_, ref mut m)) => m,
+            None => return Err(s.error(format!("'{}' before any 'newmtl'", s.keyword)))
+        };
+
+        match s.keyword {
+            "Kd" => m.kd = s.vec3()?,
+            "Ks" => m.ks = s.vec3()?,
+            "Ns" => m.ns = s.float()?,
+            "Ni" => m.ni = s.float()?,
+            "d" => m.d = s.float()?,
+            "Tr" => m.d = 1.0 - s.float()?,
+            "illum" => {
+                let i = s.float()?;
+                if i < 0.0 || i.fract() != 0.0 {
+                    return Err(s.error(format!("'illum': bad model {}", i)));
+                }
+                m.illum = i as u32;
+            }
+            "Ke" => m.ke = s.vec3()?,
+            "Pr" | "Pm" | "Ps" | "Pc" | "Pcr" => {
+                let value = s.float()?;
+                let p = m.pbr.get_or_insert_with(PrincipledParameters::default);
+                match s.keyword {
+                    "Pr" => p.roughness = value,
+                    "Pm" => p.metallic = value,
+                    "Ps" => p.sheen = value,
+                    "Pc" => p.clearcoat = value,
+                    _ => p.clearcoat_gloss = 1.0 - value
+                }
+            }
+            "map_Kd" => {
+                // The file name comes last, after any options, which are ignored
+                let file = match s.tokens.by_ref().last() {
+                    Some(f) => path.parent().unwrap_or(Path::new("")).join(f),
+                    None => return Err(s.error("'map_Kd': missing file name".to_string()))
+                };
+                let map = ImageTexture::load(&file, Filter::Trilinear, Wrap::Repeat)
+                    .map_err(|e| s.error(format!("{}: {}", file.display(), e)))?;
+                m.map_kd = Some(Arc::new(map));
+            }
+            // Ka, other texture maps and vendor extensions have no equivalent here
+            _ => continue
+        }
+        s.end()?;
+    }
+
+    if let Some((name, m)) = current {
+        materials.insert(name, m);
+    }
+
+    Ok(())
+}
+
+// Triangles sharing a material. OBJ indexes positions, UVs and normals separately,
+// so each distinct combination becomes one mesh vertex.
+//
+struct MeshBuilder {
+    material: MtlMaterial,
+    vertex_map: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+    positions: Vec<Vec3>,
+    uvs: Vec<Option<(f32, f32)>>,
+    normals: Vec<Option<Vec3>>,
+    indices: Vec<[usize; 3]>
+}
+
+impl MeshBuilder {
+    fn new(material: MtlMaterial) -> MeshBuilder {
+        MeshBuilder { material, vertex_map: HashMap::new(), positions: Vec::new(), uvs: Vec::new(), normals: Vec::new(), indices: Vec::new() }
+    }
+
+    fn vertex(&mut self, key: (usize, Option<usize>, Option<usize>), v: &[Vec3], vt: &[(f32, f32)], vn: &[Vec3]) -> usize {
+        let positions = &mut self.positions;
+        let uvs = &mut self.uvs;
+        let normals = &mut self.normals;
+        *self.vertex_map.entry(key).or_insert_with(|| {
+            positions.push(v[key.0]);
+            uvs.push(key.1.map(|i| vt[i]));
+            normals.push(key.2.map(|i| vn[i]));
+            positions.len() - 1
+        })
+    }
+
+    fn build(self) -> TriangleMesh {
+        // Smooth shading only if every vertex has a normal, otherwise fall back to flat
+        let normals = if self.normals.iter().all(|n| n.is_some()) {
+            Some(self.normals.into_iter().map(|n| n.unwrap()).collect())
+        } else {
+            None
+        };
+
+        let uvs = if self.uvs.iter().any(|uv| uv.is_some()) {
+            Some(self.uvs.into_iter().map(|uv| uv.unwrap_or((0.0, 0.0))).collect())
+        } else {
+            None
+        };
+
+        TriangleMesh::new(self.positions, self.indices, normals, uvs, self.material.to_material())
+    }
+}
+
+// Turn a 1-based (or negative, relative to the end) OBJ index into a 0-based one
+//
+fn resolve_index(s: &Statement, token: &str, count: usize) -> Result<usize, LoadError> {
+    let i = token.parse::<i64>().map_err(|_| s.error(format!("'f': bad index '{}'", token)))?;
+    let r = if i > 0 { i - 1 } else { count as i64 + i };
+    if i == 0 || r < 0 || r >= count as i64 {
+        return Err(s.error(format!("'f': index {} out of range (have {})", i, count)));
+    }
+    Ok(r as usize)
+}
+
+pub fn load_obj(path: &Path) -> Result<HitableList, LoadError> {
+    let text = fs::read_to_string(path).map_err(|e| LoadError::io(path, e))?;
+
+    let mut materials = HashMap::new();
+    let mut v = Vec::new();
+    let mut vt = Vec::new();
+    let mut vn = Vec::new();
+
+    // Meshes by material name, in order of first use
+    let mut meshes: Vec<MeshBuilder> = Vec::new();
+    let mut mesh_names: HashMap<String, usize> = HashMap::new();
+    let mut current: Option<usize> = None;
+
+    for mut s in statements(path, &text) {
+        match s.keyword {
+            "v" => {
+                v.push(s.vec3()?);
+                // Optional w
+                s.next_float()?;
+            }
+            "vt" => {
+                let u = s.float()?;
+                let tv = s.next_float()?.unwrap_or(0.0);
+                s.next_float()?;
+                vt.push((u, tv));
+            }
+            "vn" => vn.push(s.vec3()?),
+            "f" => {
+                let mut face = Vec::new();
+                for token in s.tokens.clone() {
+                    let mut parts = token.split('/');
+                    let vi = resolve_index(&s, parts.next().unwrap_or(""), v.len())?;
+                    let ti = match parts.next() {
+                        Some(t) if !t.is_empty() => Some(resolve_index(&s, t, vt.len())?),
+                        _ => None
+                    };
+                    let ni = match parts.next() {
+                        Some(n) if !n.is_empty() => Some(resolve_index(&s, n, vn.len())?),
+                        _ => None
+                    };
+                    if parts.next().is_some() {
+                        return Err(s.error(format!("'f': bad vertex '{}'", token)));
+                    }
+                    face.push((vi, ti, ni));
+                }
+                if face.len() < 3 {
+                    return Err(s.error(format!("'f': need at least 3 vertices, got {}", face.len())));
+                }
+
+                let m = match current {
+                    Some(m) => m,
+                    None => {
+                        meshes.push(MeshBuilder::new(MtlMaterial::new()));
+                        current = Some(meshes.len() - 1);
+                        meshes.len() - 1
+                    }
+                };
+                let mesh = &mut meshes[m];
+                let f: Vec<usize> = face.into_iter().map(|key| mesh.vertex(key, &v, &vt, &vn)).collect();
+                for i in 1..f.len() - 1 {
+                    mesh.indices.push([f[0], f[i], f[i + 1]]);
+                }
+                continue;
+            }
+            "mtllib" => {
+                let dir = path.parent().unwrap_or(Path::new(""));
+                for name in s.tokens.by_ref() {
+                    load_mtl(&dir.join(name), &mut materials)?;
+                }
+            }
+            "usemtl" => {
+                let name = s.name()?;
+                let material = match materials.get(name) {
+                    Some(m) => m.clone(),
+                    None => return Err(s.error(format!("'usemtl': unknown material '{}'", name)))
+                };
+                current = Some(*mesh_names.entry(name.to_string()).or_insert_with(|| {
+                    meshes.push(MeshBuilder::new(material));
+                    meshes.len() - 1
+                }));
+            }
+            // Grouping, smoothing groups, lines, points and free-form surfaces are not rendered
+            _ => continue
+        }
+        s.end()?;
+    }
+
+    let mut list = HitableList::new(Vec::new());
+    for mesh in meshes {
+        if !mesh.indices.is_empty() {
+            list.push(Box::new(mesh.build()));
+        }
+    }
+
+    Ok(list)
+}
@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+
+use output::*;
+
+// Command line options
+//
+
+pub const USAGE: &str = "\
+Usage: rustrace [options] [scene]
+
+Arguments:
+  scene                   'random' for the book's scene (default), or a scene file
+
+Options:
+  -w, --width <n>         image width in pixels (default 200)
+  -H, --height <n>        image height in pixels (default 150)
+  -s, --samples <n>       samples per pixel (default 100)
+  -d, --max-depth <n>     maximum bounces per path (default 50)
+  -o, --output <file>     output image (default out.ppm)
+  -f, --format <fmt>      output format: ppm, png or exr (default from
+                          the output extension)
+      --seed <n>          random seed (default 0)
+  -j, --threads <n>       render threads (default: one per core)
+      --spectral          trace wavelengths of light rather than RGB, for
+                          dispersion, measured metals and blackbody lights
+  -h, --help              show this help
+";
+
+pub struct Options {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_depth: usize,
+    pub scene: String,
+    pub output: PathBuf,
+    pub format: OutputFormat,
+    pub seed: u64,
+    pub threads: usize,
+    pub spectral: bool
+}
+
+fn number<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("{}: bad value '{}'", name, value))
+}
+
+fn positive(name: &str, value: &str) -> Result<usize, String> {
+    match number::<usize>(name, value)? {
+        0 => Err(format!("{}: must be at least 1", name)),
+        n => Ok(n)
+    }
+}
+
+// Returns None if help was asked for
+//
+pub fn parse_args<I: Iterator<Item=String>>(mut args: I) -> Result<Option<Options>, String> {
+    let mut o = Options {
+        width: 200,
+        height: 150,
+        samples: 100,
+        max_depth: 50,
+        scene: "random".to_string(),
+        output: PathBuf::from("out.ppm"),
+        format: OutputFormat::Ppm,
+        seed: 0,
+        threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        spectral: false
+    };
+    let mut format = None;
+    let mut scene = None;
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            if scene.is_some() {
+                return Err(format!("unexpected argument '{}'", arg));
+            }
+            scene = Some(arg);
+            continue;
+        }
+
+        // Accept both '--name value' and '--name=value'
+        let (name, inline) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+            _ => (arg.clone(), None)
+        };
+
+        if name == "-h" || name == "--help" {
+            return Ok(None);
+        }
+        if name == "--spectral" {
+            o.spectral = true;
+            continue;
+        }
+
+        let value = match inline {
+            Some(v) => v,
+            None => match args.next() {
+                Some(v) => v,
+                None => return Err(format!("{}: missing value", name))
+            }
+        };
+
+        match name.as_str() {
+            "-w" | "--width" => o.width = positive(&name, &value)?,
+            "-H" | "--height" => o.height = positive(&name, &value)?,
+            "-s" | "--samples" => o.samples = positive(&name, &value)?,
+            "-d" | "--max-depth" => o.max_depth = number(&name, &value)?,
+            "-o" | "--output" => o.output = PathBuf::from(value),
+            "-f" | "--format" => format = Some(OutputFormat::from_name(&value).ok_or_else(|| format!("{}: unknown format '{}'", name, value))?),
+            "--seed" => o.seed = number(&name, &value)?,
+            "-j" | "--threads" => o.threads = positive(&name, &value)?,
+            _ => return Err(format!("unknown option '{}'", name))
+        }
+    }
+
+    if let Some(s) = scene {
+        o.scene = s;
+    }
+
+    // Otherwise go by the output file's extension
+    o.format = match format {
+        Some(f) => f,
+        None => OutputFormat::from_path(&o.output).ok_or_else(|| format!("can't tell output format from '{}' - use --format", o.output.display()))?
+    };
+
+    Ok(Some(o))
+}
@@ -0,0 +1,116 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image;
+use image::{ImageFormat, Rgb32FImage, RgbImage};
+
+use render::*;
+
+// Writing the framebuffer out as an image file
+//
+// 8 bit formats get the gamma 2 encoding, OpenEXR keeps the linear radiance as 32 bit floats.
+//
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Ppm,
+    Png,
+    Exr
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name.to_lowercase().as_str() {
+            "ppm" => Some(OutputFormat::Ppm),
+            "png" => Some(OutputFormat::Png),
+            "exr" => Some(OutputFormat::Exr),
+            _ => None
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<OutputFormat> {
+        path.extension().and_then(|e| e.to_str()).and_then(OutputFormat::from_name)
+    }
+
+    pub fn writer(self) -> Box<dyn ImageWriter> {
+        match self {
+            OutputFormat::Ppm => Box::new(PpmWriter),
+            OutputFormat::Png => Box::new(PngWriter),
+            OutputFormat::Exr => Box::new(ExrWriter)
+        }
+    }
+}
+
+pub trait ImageWriter {
+    fn write(&self, fb: &Framebuffer, path: &Path) -> io::Result<()>;
+}
+
+// Gamma corrected 8 bit RGB, top row first
+//
+fn to_8bit(fb: &Framebuffer) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(fb.width() * fb.height() * 3);
+
+    for y in 0..fb.height() {
+        for x in 0..fb.width() {
+            let col = fb.get(x, y);
+            for c in &[col.r(), col.g(), col.b()] {
+                bytes.push((255.99 * c.max(0.0).sqrt()).min(255.0) as u8);
+            }
+        }
+    }
+
+    bytes
+}
+
+fn image_error(e: image::ImageError) -> io::Error {
+    match e {
+        image::ImageError::IoError(e) => e,
+        e => io::Error::other(e)
+    }
+}
+
+// Binary PPM
+//
+pub struct PpmWriter;
+
+impl ImageWriter for PpmWriter {
+    fn write(&self, fb: &Framebuffer, path: &Path) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        write!(file, "P6\n{width} {height}\n255\n", width=fb.width(), height=fb.height())?;
+        file.write_all(&to_8bit(fb))?;
+        file.flush()
+    }
+}
+
+// 8 bit PNG, for previews
+//
+pub struct PngWriter;
+
+impl ImageWriter for PngWriter {
+    fn write(&self, fb: &Framebuffer, path: &Path) -> io::Result<()> {
+        let img = RgbImage::from_raw(fb.width() as u32, fb.height() as u32, to_8bit(fb)).unwrap();
+        img.save_with_format(path, ImageFormat::Png).map_err(image_error)
+    }
+}
+
+// 32 bit float OpenEXR, linear radiance
+//
+pub struct ExrWriter;
+
+impl ImageWriter for ExrWriter {
+    fn write(&self, fb: &Framebuffer, path: &Path) -> io::Result<()> {
+        let mut data = Vec::with_capacity(fb.width() * fb.height() * 3);
+        for y in 0..fb.height() {
+            for x in 0..fb.width() {
+                let col = fb.get(x, y);
+                data.extend_from_slice(&[col.r(), col.g(), col.b()]);
+            }
+        }
+
+        let img = Rgb32FImage::from_raw(fb.width() as u32, fb.height() as u32, data).unwrap();
+        img.save_with_format(path, ImageFormat::OpenExr).map_err(image_error)
+    }
+}
@@ -0,0 +1,261 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use vec3::*;
+use ray::*;
+use material::*;
+use microfacet::*;
+use texture::*;
+
+// Principled BSDF - one material with the parameters artists know from other renderers
+// (Burley, "Physically Based Shading at Disney", 2012, with transmission as in his 2015 notes)
+//
+// It is a blend of lobes:
+//   diffuse       Disney's retro-reflective diffuse, plus sheen at grazing angles for cloth
+//   specular      GGX reflection, tinted towards the base colour as it becomes metallic
+//   clearcoat     a second, clear GGX-like (GTR1) layer of varnish
+//   transmission  GGX refraction, for glass and liquids, tinted by the base colour
+//
+// The dielectric's index comes from the specular parameter - 0.5 is an index of 1.5, reflecting
+// 4% head on - so reflection and refraction agree. Roughness is kept above 0.05, so that every
+// lobe has a density for light sampling to weigh against.
+//
+
+#[derive(Copy, Clone)]
+pub struct PrincipledParameters {
+    pub metallic: f32,
+    pub roughness: f32,
+    pub specular: f32,
+    pub specular_tint: f32,
+    pub sheen: f32,
+    pub sheen_tint: f32,
+    pub clearcoat: f32,
+    pub clearcoat_gloss: f32,
+    pub transmission: f32
+}
+
+impl Default for PrincipledParameters {
+    fn default() -> PrincipledParameters {
+        PrincipledParameters { metallic: 0.0, roughness: 0.5, specular: 0.5, specular_tint: 0.0, sheen: 0.0, sheen_tint: 0.5,
+                               clearcoat: 0.0, clearcoat_gloss: 1.0, transmission: 0.0 }
+    }
+}
+
+pub struct Principled {
+    base: Arc<dyn Texture>,
+    p: PrincipledParameters,
+    ior: f32,
+    specular: Ggx,
+    clearcoat_alpha: f32
+}
+
+// Lobes, and how much they're weighted by, at one point on the surface
+struct Lobes {
+    base: Vec3,
+    tint: Vec3,             // Base colour's hue, at unit luminance
+    diffuse: f32,
+    clearcoat: f32,
+    transmission: f32
+}
+
+fn luminance(c: Vec3) -> f32 {
+    0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+}
+
+fn lerp(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    (1.0 - t) * a + t * b
+}
+
+fn schlick_weight(cosine: f32) -> f32 {
+    (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+// Berry's distribution, which has a longer tail than GGX, for the clearcoat
+fn gtr1(cos_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_h * cos_h))
+}
+
+impl Principled {
+    pub fn new(base: Arc<dyn Texture>, p: PrincipledParameters) -> Principled {
+        let p = PrincipledParameters {
+            metallic: p.metallic.clamp(0.0, 1.0),
+            roughness: p.roughness.clamp(0.05, 1.0),
+            specular: p.specular.clamp(0.0, 1.0),
+            specular_tint: p.specular_tint.clamp(0.0, 1.0),
+            sheen: p.sheen.clamp(0.0, 1.0),
+            sheen_tint: p.sheen_tint.clamp(0.0, 1.0),
+            clearcoat: p.clearcoat.clamp(0.0, 1.0),
+            clearcoat_gloss: p.clearcoat_gloss.clamp(0.0, 1.0),
+            transmission: p.transmission.clamp(0.0, 1.0)
+        };
+        // Head on reflectance is 0.08 * specular
+        let r = (0.08 * p.specular).sqrt().min(0.99);
+        let ior = ((1.0 + r) / (1.0 - r)).max(1.0 + 1e-4);
+        Principled { base, p, ior, specular: Ggx::new(p.roughness), clearcoat_alpha: 0.1 + (0.001 - 0.1) * p.clearcoat_gloss }
+    }
+
+    fn lobes(&self, rec: &Hit) -> Lobes {
+        let base = self.base.value(rec.u, rec.v, rec.p, rec.uv_width);
+        let l = luminance(base);
+        let p = &self.p;
+        Lobes {
+            base,
+            tint: if l > 0.0 { base / l } else { Vec3::new(1.0, 1.0, 1.0) },
+            diffuse: (1.0 - p.metallic) * (1.0 - p.transmission),
+            clearcoat: 0.25 * p.clearcoat,
+            transmission: (1.0 - p.metallic) * p.transmission
+        }
+    }
+
+    // Chances of sampling diffuse, specular, clearcoat and transmission - rough guesses at how
+    // much each one gives, with glass mostly transmitting
+    fn lobe_probabilities(&self, lobes: &Lobes) -> [f32; 4] {
+        let w = [lobes.diffuse, 1.0 - 0.75 * lobes.transmission, lobes.clearcoat, lobes.transmission];
+        let total: f32 = w.iter().sum();
+        [w[0] / total, w[1] / total, w[2] / total, w[3] / total]
+    }
+
+    // The frame of the side the ray arrived on, the direction back along the ray in it, and
+    // the index of the far side relative to this one
+    fn local(&self, r_in: &Ray, rec: &Hit) -> (Frame, Vec3, f32) {
+        let entering = dot(r_in.direction(), rec.n) < 0.0;
+        let (n, eta) = if entering { (rec.n, self.ior) } else { (-rec.n, 1.0 / self.ior) };
+        let frame = Frame::new(n);
+        let wo = frame.to_local(-unit_vector(r_in.direction()));
+        (frame, wo, eta)
+    }
+
+    // BSDF * cosine and the density of sampling wi, both in the local frame
+    fn eval_pdf(&self, lobes: &Lobes, wo: Vec3, wi: Vec3, eta: f32) -> (Vec3, f32) {
+        let p = &self.p;
+        let prob = self.lobe_probabilities(lobes);
+        let mut value = Vec3::zero();
+        let mut pdf = 0.0;
+
+        if wo.z() <= 0.0 {
+            return (value, pdf);
+        }
+
+        if wi.z() > 0.0 {
+            let h = unit_vector(wo + wi);
+            let cos_d = dot(wi, h);
+
+            // Diffuse, brighter at grazing angles on rough surfaces, and sheen
+            let fd90 = 0.5 + 2.0 * p.roughness * cos_d * cos_d;
+            let fd = |c: f32| 1.0 + (fd90 - 1.0) * schlick_weight(c);
+            let sheen = p.sheen * schlick_weight(cos_d) * lerp(Vec3::new(1.0, 1.0, 1.0), lobes.tint, p.sheen_tint);
+            value += lobes.diffuse * (lobes.base * (fd(wo.z()) * fd(wi.z()) / PI) + sheen) * wi.z();
+            pdf += prob[0] * wi.z() / PI;
+
+            // Specular, between a tinted dielectric and the metal's own colour
+            if let Some((m, spec, spec_pdf)) = self.specular.reflection(wo, wi) {
+                let dielectric = fresnel_dielectric(dot(wo, m), eta) * lerp(Vec3::new(1.0, 1.0, 1.0), lobes.tint, p.specular_tint);
+                let metal = lerp(lobes.base, Vec3::new(1.0, 1.0, 1.0), schlick_weight(dot(wo, m)));
+                value += lerp(dielectric, metal, p.metallic) * spec;
+                pdf += prob[1] * spec_pdf;
+            }
+
+            // Clearcoat, with a fixed index of 1.5 and shadowing of a fixed roughness
+            if lobes.clearcoat > 0.0 {
+                let f = 0.04 + 0.96 * schlick_weight(cos_d);
+                let d = gtr1(h.z(), self.clearcoat_alpha);
+                let g = Ggx::new(0.5).g2(wo, wi);
+                value += Vec3::new(1.0, 1.0, 1.0) * (lobes.clearcoat * f * d * g / (4.0 * wo.z()));
+                pdf += prob[2] * d * h.z() / (4.0 * cos_d);
+            }
+        } else if let Some((m, trans, trans_pdf)) = self.specular.refraction(wo, wi, eta) {
+            // Tinted by the root of the base colour each way, so the colour once through a solid
+            let t = 1.0 - fresnel_dielectric(dot(wo, m), eta);
+            let colour = Vec3::new(lobes.base.r().sqrt(), lobes.base.g().sqrt(), lobes.base.b().sqrt());
+            value += colour * (lobes.transmission * t * trans);
+            pdf += prob[3] * trans_pdf;
+        }
+
+        (value, pdf)
+    }
+
+    // A direction from one of the lobes, picked by lobe_probabilities(). Facets can send a
+    // reflection below the surface or a refraction above it, which is lost.
+    fn sample(&self, lobes: &Lobes, wo: Vec3, eta: f32) -> Option<Vec3> {
+        let prob = self.lobe_probabilities(lobes);
+        let above = |wi: &Vec3| wi.z() > 0.0;
+        let u = rand();
+        if u < prob[0] {
+            let wi = Vec3::new(0.0, 0.0, 1.0) + random_unit_vector();
+            if wi.length_squared() < 1e-8 { None } else { Some(unit_vector(wi)) }
+        } else if u < prob[0] + prob[1] {
+            Some(reflect(-wo, self.specular.sample_visible(wo))).filter(above)
+        } else if u < prob[0] + prob[1] + prob[2] {
+            let a2 = self.clearcoat_alpha * self.clearcoat_alpha;
+            let cos_h = ((1.0 - a2.powf(1.0 - rand())) / (1.0 - a2)).max(0.0).sqrt();
+            let sin_h = (1.0 - cos_h * cos_h).max(0.0).sqrt();
+            let phi = 2.0 * PI * rand();
+            Some(reflect(-wo, Vec3::new(sin_h * phi.cos(), sin_h * phi.sin(), cos_h))).filter(above)
+        } else {
+            refract(-wo, self.specular.sample_visible(wo), 1.0 / eta).filter(|wi| !above(wi))
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, r_in: &Ray, rec: &Hit) -> Option<Scattered> {
+        let (frame, wo, eta) = self.local(r_in, rec);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let lobes = self.lobes(rec);
+        let wi = self.sample(&lobes, wo, eta)?;
+        let (value, pdf) = self.eval_pdf(&lobes, wo, wi, eta);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(Scattered { scattered: Ray::new(rec.p, frame.to_world(wi)), attenuation: value / pdf, pdf })
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> Vec3 {
+        let (frame, wo, eta) = self.local(r_in, rec);
+        self.eval_pdf(&self.lobes(rec), wo, frame.to_local(unit_vector(wi)), eta).0
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &Hit, wi: Vec3) -> f32 {
+        let (frame, wo, eta) = self.local(r_in, rec);
+        self.eval_pdf(&self.lobes(rec), wo, frame.to_local(unit_vector(wi)), eta).1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parameters outside 0 - 1 are clamped, rather than giving negative or NaN radiance
+    #[test]
+    fn out_of_range_parameters_stay_finite() {
+        let d = PrincipledParameters { specular: 0.0, ..Default::default() };
+        let cases = [
+            PrincipledParameters { sheen: -1.0, ..d },
+            PrincipledParameters { sheen: 1.0, sheen_tint: -3.0, ..d },
+            PrincipledParameters { specular: 0.5, specular_tint: -2.0, ..d },
+            PrincipledParameters { clearcoat: -1.0, ..d },
+            PrincipledParameters { clearcoat: 1.0, clearcoat_gloss: -9.1, ..d }
+        ];
+        let ok = |v: f32| v.is_finite() && v >= 0.0;
+
+        for p in cases.iter() {
+            let m = Principled::new(Arc::new(SolidColour::new(Vec3::new(0.0, 0.0, 0.0))), *p);
+            let rec = Hit::new(1.0, Vec3::zero(), Vec3::new(0.0, 0.0, 1.0), &m);
+            let r_in = Ray::new(Vec3::new(0.9, 0.0, 0.3), Vec3::new(-0.9, 0.0, -0.3));
+            for _ in 0..1000 {
+                let wi = random_unit_vector();
+                let value = m.eval(&r_in, &rec, wi);
+                assert!(ok(value.r()) && ok(value.g()) && ok(value.b()), "eval gave {}", value);
+                assert!(ok(m.pdf(&r_in, &rec, wi)));
+                if let Some(s) = m.scatter(&r_in, &rec) {
+                    let a = s.attenuation;
+                    assert!(ok(a.r()) && ok(a.g()) && ok(a.b()) && ok(s.pdf), "scatter gave {}", a);
+                }
+            }
+        }
+    }
+}
@@ -7,13 +7,95 @@ use vec3::*;
 pub struct Ray {
     a: Vec3,
     b: Vec3,
+    spread: f32,                    // Angle the ray's cone widens by - zero for a ray that is a line
+    time: f32,                      // When the ray is, for motion blur
+    wavelength: f32,                // Nanometres, once something that disperses light has picked one - zero until then
+    interiors: Option<Interiors>    // Which dielectrics the ray is inside, once one has said
 }
 
 impl Ray {
-    pub fn new(ia: Vec3, ib: Vec3) -> Ray { Ray { a:ia, b:ib } }
+    pub fn new(ia: Vec3, ib: Vec3) -> Ray { Ray { a:ia, b:ib, spread:0.0, time:0.0, wavelength:0.0, interiors:None } }
+    pub fn with_spread(&self, s: f32) -> Ray { Ray { spread:s, ..*self } }
+    pub fn with_time(&self, t: f32) -> Ray { Ray { time:t, ..*self } }
+    pub fn with_wavelength(&self, w: f32) -> Ray { Ray { wavelength:w, ..*self } }
+    pub fn with_interiors(&self, i: Interiors) -> Ray { Ray { interiors:Some(i), ..*self } }
+
+    // The same ray, along a different line - as seen from another space
+    pub fn with_line(&self, ia: Vec3, ib: Vec3) -> Ray { Ray { a:ia, b:ib, ..*self } }
+
     pub fn origin(&self) -> Vec3 { self.a }
     pub fn direction(&self) -> Vec3 { self.b }
+    pub fn spread(&self) -> f32 { self.spread }
+    pub fn time(&self) -> f32 { self.time }
+    pub fn wavelength(&self) -> f32 { self.wavelength }
+    pub fn interiors(&self) -> Interiors { self.interiors.unwrap_or_default() }
+
+    // Whether the ray says which dielectrics it is in, rather than carrying on in those it came from
+    pub fn has_interiors(&self) -> bool { self.interiors.is_some() }
     pub fn point_at_parameter(&self, t: f32) -> Vec3 { self.a + t*self.b }
+
+    // Width of the ray's cone at t
+    pub fn footprint(&self, t: f32) -> f32 { self.spread * t * self.b.length() }
+}
+
+// Dielectrics a ray is inside, for glass, liquids and bubbles nested in each other (Schmidt and
+// Budge, "Simple Nested Dielectrics in Ray Traced Images", 2002)
+//
+// Where dielectrics overlap the ray is in the one of highest priority, or the latest entered of
+// equals, and the surfaces of the others within it are ignored. That way a liquid can be made
+// a little larger than the inside of its glass, and the glass decides where the two meet.
+//
+#[derive(Copy, Clone, Debug)]
+pub struct Interior {
+    pub id: usize,          // Which dielectric, to leave the right one
+    pub ior: f32,           // At the ray's wavelength when it went in
+    pub absorption: Vec3,
+    pub priority: i32
+}
+
+pub const MAX_INTERIORS: usize = 4;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Interiors {
+    stack: [Interior; MAX_INTERIORS],
+    len: usize
+}
+
+impl Default for Interiors {
+    fn default() -> Interiors {
+        let outside = Interior { id: 0, ior: 1.0, absorption: Vec3::zero(), priority: 0 };
+        Interiors { stack: [outside; MAX_INTERIORS], len: 0 }
+    }
+}
+
+impl Interiors {
+    // The dielectric the ray is actually in, or None in air
+    pub fn current(&self) -> Option<&Interior> {
+        self.stack[..self.len].iter().max_by_key(|i| i.priority)
+    }
+
+    // Having gone into another, or None if they are nested deeper than there is room for
+    pub fn with(&self, interior: Interior) -> Option<Interiors> {
+        if self.len == MAX_INTERIORS {
+            return None;
+        }
+        let mut i = *self;
+        i.stack[i.len] = interior;
+        i.len += 1;
+        Some(i)
+    }
+
+    pub fn contains(&self, id: usize) -> bool { self.stack[..self.len].iter().any(|e| e.id == id) }
+
+    // Having come out of one
+    pub fn without(&self, id: usize) -> Interiors {
+        let mut i = *self;
+        if let Some(at) = i.stack[..i.len].iter().rposition(|e| e.id == id) {
+            i.stack.copy_within(at + 1..i.len, at);
+            i.len -= 1;
+        }
+        i
+    }
 }
 
 impl fmt::Display for Ray {
@@ -0,0 +1,120 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use vec3::*;
+use scene::*;
+use integrator::*;
+use spectrum::*;
+
+// Edge length of the square tiles handed out to render threads
+const TILE_SIZE: usize = 16;
+
+// Accumulated linear radiance, stored top row first
+//
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Framebuffer {
+        Framebuffer { width, height, pixels: vec![Vec3::zero(); width * height] }
+    }
+
+    pub fn width(&self) -> usize { self.width }
+    pub fn height(&self) -> usize { self.height }
+    pub fn get(&self, x: usize, y: usize) -> Vec3 { self.pixels[y * self.width + x] }
+    pub fn set(&mut self, x: usize, y: usize, c: Vec3) { self.pixels[y * self.width + x] = c; }
+}
+
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples: usize,
+    pub max_depth: usize,
+    pub seed: u64,
+    pub threads: usize,
+    pub spectral: bool      // Trace wavelengths rather than RGB
+}
+
+struct Tile {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize
+}
+
+fn render_tile(scene: &Scene, settings: &RenderSettings, tile: &Tile) -> Vec<Vec3> {
+    let (nx, ny) = (settings.width, settings.height);
+    let spread = scene.camera.pixel_spread(ny);
+    let mut pixels = Vec::with_capacity((tile.x1 - tile.x0) * (tile.y1 - tile.y0));
+
+    for y in tile.y0..tile.y1 {
+        // Framebuffer rows go down the image, camera v goes up
+        let j = ny - 1 - y;
+        for i in tile.x0..tile.x1 {
+            let mut col = Vec3::zero();
+
+            for _s in 0..settings.samples {
+                let u = (i as f32 + rand()) / nx as f32;
+                let v = (j as f32 + rand()) / ny as f32;
+
+                let r = scene.camera.get_ray(u,v).with_spread(spread);
+                if settings.spectral {
+                    let hero = sample_hero_wavelength();
+                    col += spectrum_to_rgb(colour(&r.with_wavelength(hero), scene, settings.max_depth), hero_wavelengths(hero));
+                } else {
+                    col += colour(&r, scene, settings.max_depth);
+                }
+            }
+
+            pixels.push(col / settings.samples as f32);
+        }
+    }
+
+    pixels
+}
+
+// Render the scene on settings.threads threads, each pulling tiles from a shared counter
+//
+pub fn render(scene: &Scene, settings: &RenderSettings) -> Framebuffer {
+    let mut tiles = Vec::new();
+    for y0 in (0..settings.height).step_by(TILE_SIZE) {
+        for x0 in (0..settings.width).step_by(TILE_SIZE) {
+            tiles.push(Tile { x0, y0, x1: (x0 + TILE_SIZE).min(settings.width), y1: (y0 + TILE_SIZE).min(settings.height) });
+        }
+    }
+
+    let framebuffer = Mutex::new(Framebuffer::new(settings.width, settings.height));
+    let next_tile = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        for _ in 0..settings.threads.max(1) {
+            scope.spawn(|| {
+                loop {
+                    let t = next_tile.fetch_add(1, Ordering::Relaxed);
+                    if t >= tiles.len() {
+                        break;
+                    }
+                    let tile = &tiles[t];
+
+                    // Seed by tile, so the image does not depend on which thread drew what
+                    seed_rand(settings.seed ^ (t as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+                    let pixels = render_tile(scene, settings, tile);
+
+                    let mut fb = framebuffer.lock().unwrap();
+                    let mut p = pixels.iter();
+                    for y in tile.y0..tile.y1 {
+                        for x in tile.x0..tile.x1 {
+                            fb.set(x, y, *p.next().unwrap());
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    framebuffer.into_inner().unwrap()
+}
@@ -0,0 +1,765 @@
+use std::collections::HashMap;
+use std::fs;
+use std::mem;
+use std::path::Path;
+use std::sync::Arc;
+
+use vec3::*;
+use ray::*;
+use material::*;
+use film::*;
+use microfacet::*;
+use principled::*;
+use background::*;
+use sky::*;
+use texture::*;
+use noise::*;
+use hitable::*;
+use shapes::*;
+use matrix::*;
+use transform::*;
+use medium::*;
+use camera::*;
+use mesh::*;
+use obj::*;
+use bvh::*;
+use error::*;
+use statement::*;
+
+// Scene descriptions
+//
+// A scene file is a list of statements, one per line:
+//
+//   lookfrom x y z                             camera position
+//   lookat x y z                               point the camera looks at
+//   vup x y z                                  camera up direction (default 0 1 0)
+//   vfov degrees                               vertical field of view (default 20)
+//   aperture a                                 lens diameter (default 0 - pinhole)
+//   focus d                                    focus distance (default lookfrom to lookat)
+//   shutter open close                         times the shutter is open between, from 0 to 1 (default 0 0)
+//
+//   background r g b                           constant colour in place of the default sky gradient
+//   environment file [scale] [rotation]        equirectangular image (e.g. Radiance .hdr), relative to the scene,
+//                                              scaled in brightness and turned about y by rotation degrees
+//   sky sun x y z [turbidity]                  daylight sky and sun, with the sun in the given direction
+//   sky place latitude longitude day hour [turbidity]
+//                                              ... or where it is seen from a place (degrees north and east,
+//                                              north along -z) on a day of the year at a local time
+//
+//   texture name solid r g b
+//   texture name checker size odd even         3D checkerboard of cubes, between two colours
+//   texture name image file [filter] [wrap]    PNG, JPEG, HDR or EXR image, relative to the scene
+//                                              filter bilinear|trilinear (default), wrap repeat (default)|clamp|mirror
+//   texture name noise pattern scale [low high]
+//                                              procedural pattern, blending two colours (default black and white)
+//                                              pattern perlin|fbm|turbulence|worley|marble|wood|stone
+//
+//   material name lambertian colour
+//   material name metal colour fuzz [film thickness ior [texture]]
+//                                              thin film of thickness in nanometres and index ior, for
+//                                              iridescence, its thickness optionally scaled by a texture
+//   material name dielectric ior [absorb r g b distance] [cauchy b | sellmeier glass | sellmeier b1 b2 b3 c1 c2 c3]
+//                                [priority n] [film thickness ior [texture]]
+//                                              glass, coloured r g b after passing through distance of it,
+//                                              and splitting light by Cauchy's B (in square micrometres) or
+//                                              Sellmeier's coefficients, or those of glass bk7|sf11|fused_silica.
+//                                              Sellmeier's equation gives the whole index, so it replaces ior.
+//                                              Where dielectrics overlap, the one of highest whole number
+//                                              priority (default 0) fills the overlap. A soap bubble is a film on
+//                                              a dielectric of index 1
+//   material name conductor metal roughness    GGX metal: gold|silver|copper|aluminium|iron|chrome, roughness 0 - 1
+//   material name conductor eta_r eta_g eta_b k_r k_g k_b roughness
+//                                              ... or with the complex index of refraction given
+//   material name rough_dielectric ior roughness
+//                                              GGX frosted glass, which like principled transmission is
+//                                              always between it and air - it doesn't nest with dielectrics
+//   material name principled colour [parameter value ...]
+//                                              Disney's principled BSDF, with parameters metallic, roughness,
+//                                              specular, specular_tint, sheen, sheen_tint, clearcoat,
+//                                              clearcoat_gloss and transmission, all 0 - 1
+//   material name diffuse_light r g b
+//   material name blackbody kelvin strength    light the colour of something that hot, as bright as a
+//                                              diffuse_light of strength strength strength
+//   material name isotropic colour             scatters equally in all directions, for media
+//   material name bump base pattern scale strength
+//                                              base material with its normals perturbed by a noise pattern
+//
+// where a colour is either r g b, or the name of a texture.
+//
+//   sphere x y z radius material
+//   moving_sphere x0 y0 z0 x1 y1 z1 radius material
+//                                              sphere moving from the first centre at time 0 to the second at time 1
+//   triangle x0 y0 z0 x1 y1 z1 x2 y2 z2 material
+//   plane x y z nx ny nz material              infinite plane through a point, facing along a normal
+//   rect xy|xz|yz a0 a1 b0 b1 k material       axis aligned rectangle, e.g. xy from x0 to x1 and y0 to y1, at z = k
+//   disk x y z nx ny nz radius material
+//   box x0 y0 z0 x1 y1 z1 material             axis aligned box between two corners
+//   mesh file.obj                              OBJ file, relative to the scene, using its MTL materials
+//   random_spheres x y z clearance             the book's field of small spheres, keeping clear of a point
+//
+//   object name                                start collecting objects into a named group, kept out of
+//   ...                                        the scene, until the matching end
+//   end
+//   instance name [op ...] [to op ...] [material m]
+//                                              the named group, placed by transform ops applied in turn:
+//                                              translate x y z, rotate degrees ax ay az, scale s | scale x y z,
+//                                              optionally moving to where the ops after 'to' put it at time 1,
+//                                              and optionally drawn in a different material
+//
+//   grid name file path                        voxel densities from a grid file, relative to the scene
+//   grid name noise pattern scale resolution   a puff of cloud, from a noise pattern on a cube of voxels
+//
+//   medium name density material               the inside of the named object, which must be convex, filled
+//                                              with fog colliding density times per unit distance,
+//                                              scattering as the material (usually isotropic) says
+//   volume grid x0 y0 z0 x1 y1 z1 density material [emit r g b]
+//                                              a grid stretched over a box, density for a grid value of 1,
+//                                              glowing in proportion to the grid where emit is given
+//
+// Media can not be part of objects.
+//
+// Noise patterns are seeded from --seed, in the order they appear.
+//
+
+// Everything needed to render - objects (in a BVH, apart from unbounded ones), the ones
+// that emit light, participating media, the camera, and what rays that miss everything see
+//
+// Media are kept apart from the surfaces, so that shadow rays can go through them.
+//
+pub struct Scene {
+    pub world: HitableList,
+    pub lights: Vec<Arc<dyn Hitable>>,
+    pub media: Vec<Box<dyn Hitable>>,
+    pub camera: Camera,
+    pub background: Box<dyn Background>
+}
+
+impl Scene {
+    pub fn new(objects: HitableList, camera: Camera) -> Scene {
+        let mut lights = Vec::new();
+        let objects = objects.into_vec().into_iter().map(|o| {
+            if o.is_emissive() {
+                let light: Arc<dyn Hitable> = Arc::from(o);
+                lights.push(light.clone());
+                Box::new(light)
+            } else {
+                o
+            }
+        });
+
+        let (bounded, unbounded): (Vec<_>, Vec<_>) = objects.partition(|o| o.axis_aligned_bounding_box().is_some());
+        let mut world = HitableList::new(unbounded);
+        world.push(Box::new(BvhNode::new(bounded)));
+
+        Scene { world, lights, media: Vec::new(), camera, background: Box::new(Gradient) }
+    }
+
+    // The nearest surface, unless a medium scatters the ray before it
+    pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let mut rec = self.world.hit(r, t_min, t_max);
+        for m in &self.media {
+            let t = rec.map_or(t_max, |h| h.t);
+            if let Some(h) = m.hit(r, t_min, t) {
+                rec = Some(h);
+            }
+        }
+        rec
+    }
+
+    // Fraction of light along the ray that gets through the media between t_min and t_max
+    pub fn transmittance(&self, r: &Ray, t_min: f32, t_max: f32) -> f32 {
+        self.media.iter().map(|m| m.transmittance(r, t_min, t_max)).product()
+    }
+
+    // Number of things random_light_direction() picks from - the lights, and the background
+    // if it can be sampled
+    pub fn light_count(&self) -> usize {
+        self.lights.len() + if self.background.is_sampled() { 1 } else { 0 }
+    }
+
+    // Direction from o towards a light, chosen uniformly from all the lights
+    pub fn random_light_direction(&self, o: Vec3) -> Vec3 {
+        let i = ((rand() * self.light_count() as f32) as usize).min(self.light_count() - 1);
+        match self.lights.get(i) {
+            Some(l) => l.random(o),
+            None => self.background.random()
+        }
+    }
+
+    // Density with which random_light_direction() would choose v
+    pub fn light_pdf(&self, o: Vec3, v: Vec3) -> f32 {
+        if self.light_count() == 0 {
+            return 0.0;
+        }
+        let mut total = self.lights.iter().map(|l| l.pdf_value(o, v)).sum::<f32>();
+        if self.background.is_sampled() {
+            total += self.background.pdf_value(v);
+        }
+        total / self.light_count() as f32
+    }
+}
+
+// Marble, wood or stone, picked at random
+//
+fn random_procedural_material() -> Arc<dyn Material> {
+    let (pattern, scale, low, high) = match (rand() * 3.0) as usize {
+        0 => (Pattern::Marble, 8.0, Vec3::new(0.15, 0.15, 0.17), Vec3::new(0.92, 0.9, 0.86)),
+        1 => (Pattern::Wood, 6.0, Vec3::new(0.35, 0.18, 0.07), Vec3::new(0.7, 0.45, 0.22)),
+        _ => (Pattern::Stone, 10.0, Vec3::new(0.1, 0.1, 0.1), Vec3::new(0.6, 0.58, 0.55))
+    };
+    let seed = rand_u64();
+    let texture = NoiseTexture::new(Procedural::new(pattern, scale, seed),
+                                    Arc::new(SolidColour::new(low)),
+                                    Arc::new(SolidColour::new(high)));
+    let m: Arc<dyn Material> = Arc::new(Lambertian::textured(Arc::new(texture)));
+    if pattern == Pattern::Stone {
+        // The cracks between stones are grooves
+        Arc::new(Bump::new(m, Procedural::new(pattern, scale, seed), 0.02))
+    } else {
+        m
+    }
+}
+
+pub fn random_spheres(world: &mut HitableList, clear: Vec3, dist:f32) {
+    for a in -11 .. 11 {
+        for b in -11 .. 11 {
+            let choose_mat = rand();
+            let centre = Vec3::new(a as f32 + 0.9*rand(), 0.2, b as f32 + 0.9 * rand());
+            if (centre - clear).length() > dist {
+                if choose_mat < 0.55 {
+                    // Diffuse
+                    world.push(Box::new(Sphere::new(centre, 0.2,
+                                                    Arc::new(Lambertian::new(Vec3::new(rand()*rand(),
+                                                                                       rand()*rand(),
+                                                                                       rand()*rand()))))));
+                } else if choose_mat < 0.7 {
+                    // Procedural
+                    world.push(Box::new(Sphere::new(centre, 0.2, random_procedural_material())));
+                } else if choose_mat < 0.85 {
+                    // Metal
+                    world.push(Box::new(Sphere::new(centre, 0.2,
+                                                    Arc::new(Metal::new(Vec3::new(0.5*(1.0+rand()),
+                                                                                  0.5*(1.0+rand()),
+                                                                                  0.5*(1.0+rand())),
+                                                                        0.5*rand())))));
+                } else {
+                    // Glass
+                    world.push(Box::new(Sphere::new(centre, 0.2,Arc::new(Dielectric::new(1.5)))));
+                }
+            }
+        }
+    }
+}
+
+// The book's final scene
+//
+pub fn random_scene(aspect: f32) -> Scene {
+
+    let mut h = HitableList::new(Vec::new());
+
+    let checker = Arc::new(Checker::new(Arc::new(SolidColour::new(Vec3::new(0.2, 0.3, 0.1))),
+                                        Arc::new(SolidColour::new(Vec3::new(0.9, 0.9, 0.9))),
+                                        1.0));
+    h.push(Box::new(Plane::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), Arc::new(Lambertian::textured(checker)))));
+
+    random_spheres(&mut h, Vec3::new(4.0, 0.2, 0.0), 0.9);
+
+    h.push(Box::new(Sphere::new(Vec3::new(0.0,1.0,0.0), 1.0, Arc::new(Dielectric::new(1.5).with_dispersion(Dispersion::Cauchy { b: 0.02 })))));
+    h.push(Box::new(Sphere::new(Vec3::new(-4.0,1.0,0.0), 1.0, Arc::new(Lambertian::new(Vec3::new(0.4, 0.2, 0.1))))));
+    h.push(Box::new(Sphere::new(Vec3::new(4.0,1.0,0.0), 1.0, Arc::new(Metal::new(Vec3::new(0.7, 0.6, 0.5), 0.0)))));
+
+    let lookfrom = Vec3::new(14.0, 2.0, 3.0);
+    let lookat = Vec3::new(0.0, 0.0, 0.0);
+
+    let dist_to_focus = (lookfrom - lookat).length();
+    let aperture = 0.05;
+
+    let cam = Camera::new(lookfrom, lookat, Vec3::new(0.0,1.0,0.0), 20.0, aspect, aperture, dist_to_focus);
+
+    Scene::new(h, cam)
+}
+
+type Textures = HashMap<String, Arc<dyn Texture>>;
+type Materials = HashMap<String, Arc<dyn Material>>;
+
+// Where an instance starts, where it ends up if it moves, and its replacement material
+type Placement = (Mat4, Option<Mat4>, Option<Arc<dyn Material>>);
+
+// Either three numbers, or the name of a texture
+fn parse_colour(s: &mut Statement, textures: &Textures) -> Result<Arc<dyn Texture>, LoadError> {
+    if s.peek().is_some_and(|t| t.parse::<f32>().is_ok()) {
+        return Ok(Arc::new(SolidColour::new(s.vec3()?)));
+    }
+    let name = s.name()?;
+    match textures.get(name) {
+        Some(t) => Ok(t.clone()),
+        None => Err(s.error(format!("unknown texture '{}'", name)))
+    }
+}
+
+// Thickness in nanometres and index, then optionally a texture to scale the thickness by
+fn parse_film(s: &mut Statement, textures: &Textures) -> Result<ThinFilm, LoadError> {
+    let film = ThinFilm::new(s.float()?, s.float()?);
+    match s.peek().and_then(|name| textures.get(name)) {
+        Some(t) => {
+            s.tokens.next();
+            Ok(film.with_variation(t.clone()))
+        }
+        None => Ok(film)
+    }
+}
+
+fn parse_procedural(s: &mut Statement) -> Result<Procedural, LoadError> {
+    let name = s.name()?;
+    let pattern = match Pattern::from_name(name) {
+        Some(p) => p,
+        None => return Err(s.error(format!("unknown noise pattern '{}'", name)))
+    };
+    Ok(Procedural::new(pattern, s.float()?, rand_u64()))
+}
+
+fn parse_texture(s: &mut Statement, dir: &Path, textures: &Textures) -> Result<Arc<dyn Texture>, LoadError> {
+    let kind = s.name()?;
+    let t: Arc<dyn Texture> = match kind {
+        "solid" => Arc::new(SolidColour::new(s.vec3()?)),
+        "checker" => {
+            let size = s.float()?;
+            Arc::new(Checker::new(parse_colour(s, textures)?, parse_colour(s, textures)?, size))
+        }
+        "image" => {
+            let file = dir.join(s.name()?);
+            let (mut filter, mut wrap) = (Filter::Trilinear, Wrap::Repeat);
+            while let Some(option) = s.tokens.next() {
+                match option {
+                    "bilinear" => filter = Filter::Bilinear,
+                    "trilinear" => filter = Filter::Trilinear,
+                    "repeat" => wrap = Wrap::Repeat,
+                    "clamp" => wrap = Wrap::Clamp,
+                    "mirror" => wrap = Wrap::Mirror,
+                    _ => return Err(s.error(format!("unknown image option '{}'", option)))
+                }
+            }
+            let image = ImageTexture::load(&file, filter, wrap).map_err(|e| s.error(format!("{}: {}", file.display(), e)))?;
+            Arc::new(image)
+        }
+        "noise" => {
+            let field = parse_procedural(s)?;
+            let (low, high): (Arc<dyn Texture>, Arc<dyn Texture>) = match s.peek() {
+                Some(_) => (parse_colour(s, textures)?, parse_colour(s, textures)?),
+                None => (Arc::new(SolidColour::new(Vec3::zero())), Arc::new(SolidColour::new(Vec3::new(1.0, 1.0, 1.0))))
+            };
+            Arc::new(NoiseTexture::new(field, low, high))
+        }
+        _ => return Err(s.error(format!("unknown texture type '{}'", kind)))
+    };
+    Ok(t)
+}
+
+fn parse_material(s: &mut Statement, textures: &Textures, materials: &Materials) -> Result<Arc<dyn Material>, LoadError> {
+    let kind = s.name()?;
+    let m: Arc<dyn Material> = match kind {
+        "lambertian" => Arc::new(Lambertian::textured(parse_colour(s, textures)?)),
+        "metal" => {
+            let m = Metal::textured(parse_colour(s, textures)?, s.float()?);
+            match s.tokens.next() {
+                Some("film") => Arc::new(m.with_film(parse_film(s, textures)?)),
+                Some(other) => return Err(s.error(format!("'metal': unknown option '{}'", other))),
+                None => Arc::new(m)
+            }
+        }
+        "dielectric" => {
+            let mut d = Dielectric::new(s.float()?);
+            while let Some(option) = s.tokens.next() {
+                d = match option {
+                    "absorb" => {
+                        let (colour, distance) = (s.vec3()?, s.float()?);
+                        if distance <= 0.0 {
+                            return Err(s.error("'absorb': distance must be greater than 0".to_string()));
+                        }
+                        d.with_absorption(colour, distance)
+                    }
+                    "cauchy" => d.with_dispersion(Dispersion::Cauchy { b: s.float()? }),
+                    "priority" => d.with_priority(s.integer()?),
+                    "film" => d.with_film(parse_film(s, textures)?),
+                    "sellmeier" => {
+                        let glass = match s.peek() {
+                            Some(name) if name.parse::<f32>().is_err() => Some(s.name()?),
+                            _ => None
+                        };
+                        match glass {
+                            Some(name) => match Dispersion::named(name) {
+                                Some(dispersion) => d.with_dispersion(dispersion),
+                                None => return Err(s.error(format!("unknown glass '{}'", name)))
+                            },
+                            None => {
+                                let (b, c) = (s.vec3()?, s.vec3()?);
+                                d.with_dispersion(Dispersion::Sellmeier { b: [b.x(), b.y(), b.z()], c: [c.x(), c.y(), c.z()] })
+                            }
+                        }
+                    }
+                    _ => return Err(s.error(format!("'dielectric': unknown option '{}'", option)))
+                };
+            }
+            Arc::new(d)
+        }
+        "conductor" => {
+            let named = match s.peek() {
+                Some(name) if name.parse::<f32>().is_err() => Some(s.name()?),
+                _ => None
+            };
+            match named {
+                Some(name) => {
+                    let roughness = s.float()?;
+                    match Conductor::named(name, roughness) {
+                        Some(c) => Arc::new(c),
+                        None => return Err(s.error(format!("unknown metal '{}'", name)))
+                    }
+                }
+                None => Arc::new(Conductor::new(s.vec3()?, s.vec3()?, s.float()?))
+            }
+        }
+        "rough_dielectric" => Arc::new(RoughDielectric::new(s.float()?, s.float()?)),
+        "principled" => {
+            let base = parse_colour(s, textures)?;
+            let mut p = PrincipledParameters::default();
+            while let Some(name) = s.tokens.next() {
+                let value = match name {
+                    "metallic" => &mut p.metallic,
+                    "roughness" => &mut p.roughness,
+                    "specular" => &mut p.specular,
+                    "specular_tint" => &mut p.specular_tint,
+                    "sheen" => &mut p.sheen,
+                    "sheen_tint" => &mut p.sheen_tint,
+                    "clearcoat" => &mut p.clearcoat,
+                    "clearcoat_gloss" => &mut p.clearcoat_gloss,
+                    "transmission" => &mut p.transmission,
+                    _ => return Err(s.error(format!("'principled': unknown parameter '{}'", name)))
+                };
+                *value = s.float()?;
+            }
+            Arc::new(Principled::new(base, p))
+        }
+        "diffuse_light" => Arc::new(DiffuseLight::new(s.vec3()?)),
+        "blackbody" => Arc::new(Blackbody::new(s.float()?, s.float()?)),
+        "isotropic" => Arc::new(Isotropic::textured(parse_colour(s, textures)?)),
+        "bump" => {
+            let base = lookup_material(s, materials)?;
+            let height = parse_procedural(s)?;
+            Arc::new(Bump::new(base, height, s.float()?))
+        }
+        _ => return Err(s.error(format!("unknown material type '{}'", kind)))
+    };
+    Ok(m)
+}
+
+// Transform ops, applied in the order given, a second set after 'to' if the instance moves,
+// and an optional replacement material
+fn parse_instance(s: &mut Statement, materials: &Materials) -> Result<Placement, LoadError> {
+    let mut m = Mat4::identity();
+    let mut start = None;
+    let mut material = None;
+    while let Some(op) = s.tokens.next() {
+        let step = match op {
+            "translate" => Mat4::translate(s.vec3()?),
+            "rotate" => {
+                let degrees = s.float()?;
+                Mat4::rotate(degrees, s.vec3()?)
+            }
+            "scale" => {
+                let x = s.float()?;
+                if s.peek().is_some_and(|t| t.parse::<f32>().is_ok()) {
+                    Mat4::scale(Vec3::new(x, s.float()?, s.float()?))
+                } else {
+                    Mat4::scale(Vec3::new(x, x, x))
+                }
+            }
+            "material" => {
+                material = Some(lookup_material(s, materials)?);
+                continue;
+            }
+            "to" => {
+                if start.is_some() {
+                    return Err(s.error("'instance': more than one 'to'".to_string()));
+                }
+                start = Some(m);
+                m = Mat4::identity();
+                continue;
+            }
+            _ => return Err(s.error(format!("'instance': unknown transform '{}'", op)))
+        };
+        m = step * m;
+    }
+
+    let (start, end) = match start {
+        Some(start) => (start, Some(m)),
+        None => (m, None)
+    };
+    Ok((start, end, material))
+}
+
+fn lookup_material(s: &mut Statement, materials: &Materials) -> Result<Arc<dyn Material>, LoadError> {
+    let name = s.name()?;
+    match materials.get(name) {
+        Some(m) => Ok(m.clone()),
+        None => Err(s.error(format!("unknown material '{}'", name)))
+    }
+}
+
+pub fn load_scene(path: &Path, aspect: f32) -> Result<Scene, LoadError> {
+    let text = fs::read_to_string(path).map_err(|e| LoadError::io(path, e))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut lookfrom = None;
+    let mut lookat = None;
+    let mut vup = Vec3::new(0.0, 1.0, 0.0);
+    let mut vfov = 20.0;
+    let mut aperture = 0.0;
+    let mut focus = None;
+    let mut shutter = (0.0, 0.0);
+    let mut background: Option<Box<dyn Background>> = None;
+
+    let mut textures = HashMap::new();
+    let mut materials = HashMap::new();
+    let mut objects: HashMap<String, Arc<dyn Hitable>> = HashMap::new();
+    let mut grids: HashMap<String, Arc<DensityGrid>> = HashMap::new();
+    let mut h = HitableList::new(Vec::new());
+    let mut media: Vec<Box<dyn Hitable>> = Vec::new();
+
+    // While an object is being defined, h collects its contents, and the scene so far waits here
+    let mut outer: Option<(String, HitableList)> = None;
+
+    for mut s in statements(path, &text) {
+        match s.keyword {
+            "lookfrom" => lookfrom = Some(s.vec3()?),
+            "lookat" => lookat = Some(s.vec3()?),
+            "vup" => vup = s.vec3()?,
+            "vfov" => vfov = s.float()?,
+            "aperture" => aperture = s.float()?,
+            "focus" => focus = Some(s.float()?),
+            "shutter" => {
+                let (open, close) = (s.float()?, s.float()?);
+                // Moving objects only know where they are between times 0 and 1
+                if !(0.0 <= open && open <= close && close <= 1.0) {
+                    return Err(s.error("'shutter': times must be from 0 to 1, opening before closing".to_string()));
+                }
+                shutter = (open, close);
+            }
+
+            "background" => background = Some(Box::new(Constant::new(s.vec3()?))),
+            "sky" => {
+                let sun = match s.name()? {
+                    "sun" => s.vec3()?,
+                    "place" => {
+                        let (latitude, longitude) = (s.float()?, s.float()?);
+                        let (day, hour) = (s.float()?, s.float()?);
+                        sun_direction(latitude, longitude, day, hour)
+                    }
+                    other => return Err(s.error(format!("'sky': expected 'sun' or 'place', not '{}'", other)))
+                };
+                let turbidity = s.next_float()?.unwrap_or(3.0);
+                background = Some(Box::new(Sky::new(sun, turbidity)));
+            }
+            "environment" => {
+                let file = dir.join(s.name()?);
+                let scale = s.next_float()?.unwrap_or(1.0);
+                let rotation = s.next_float()?.unwrap_or(0.0);
+                let map = EnvironmentMap::load(&file, scale, rotation).map_err(|e| s.error(format!("{}: {}", file.display(), e)))?;
+                background = Some(Box::new(map));
+            }
+
+            "texture" => {
+                let name = s.name()?.to_string();
+                if textures.contains_key(&name) {
+                    return Err(s.error(format!("texture '{}' already defined", name)));
+                }
+                let t = parse_texture(&mut s, dir, &textures)?;
+                textures.insert(name, t);
+            }
+            "material" => {
+                let name = s.name()?.to_string();
+                if materials.contains_key(&name) {
+                    return Err(s.error(format!("material '{}' already defined", name)));
+                }
+                let m = parse_material(&mut s, &textures, &materials)?;
+                materials.insert(name, m);
+            }
+
+            "sphere" => {
+                let centre = s.vec3()?;
+                let radius = s.float()?;
+                let m = lookup_material(&mut s, &materials)?;
+                h.push(Box::new(Sphere::new(centre, radius, m)));
+            }
+            "moving_sphere" => {
+                let (centre0, centre1) = (s.vec3()?, s.vec3()?);
+                let radius = s.float()?;
+                let m = lookup_material(&mut s, &materials)?;
+                h.push(Box::new(MovingSphere::new(centre0, centre1, radius, m)));
+            }
+            "triangle" => {
+                let (p0, p1, p2) = (s.vec3()?, s.vec3()?, s.vec3()?);
+                let m = lookup_material(&mut s, &materials)?;
+                h.push(Box::new(Triangle::new(p0, p1, p2, m)));
+            }
+            "plane" => {
+                let (point, normal) = (s.vec3()?, s.vec3()?);
+                let m = lookup_material(&mut s, &materials)?;
+                h.push(Box::new(Plane::new(point, normal, m)));
+            }
+            "rect" => {
+                let axis = match s.name()? {
+                    "yz" => 0,
+                    "xz" => 1,
+                    "xy" => 2,
+                    other => return Err(s.error(format!("'rect': expected xy, xz or yz, not '{}'", other)))
+                };
+                let (a0, a1, b0, b1, k) = (s.float()?, s.float()?, s.float()?, s.float()?, s.float()?);
+                let m = lookup_material(&mut s, &materials)?;
+                h.push(Box::new(Rect::new(axis, a0, a1, b0, b1, k, m)));
+            }
+            "disk" => {
+                let (centre, normal, radius) = (s.vec3()?, s.vec3()?, s.float()?);
+                let m = lookup_material(&mut s, &materials)?;
+                h.push(Box::new(Disk::new(centre, normal, radius, m)));
+            }
+            "box" => {
+                let (p0, p1) = (s.vec3()?, s.vec3()?);
+                let m = lookup_material(&mut s, &materials)?;
+                h.push(Box::new(Cuboid::new(p0, p1, m)));
+            }
+            "mesh" => {
+                let file = s.name()?;
+                for o in load_obj(&dir.join(file))?.into_vec() {
+                    h.push(o);
+                }
+            }
+            "random_spheres" => {
+                let clear = s.vec3()?;
+                let dist = s.float()?;
+                random_spheres(&mut h, clear, dist);
+            }
+
+            "object" => {
+                let name = s.name()?.to_string();
+                if outer.is_some() {
+                    return Err(s.error("'object': objects can not be nested".to_string()));
+                }
+                if objects.contains_key(&name) {
+                    return Err(s.error(format!("object '{}' already defined", name)));
+                }
+                outer = Some((name, mem::replace(&mut h, HitableList::new(Vec::new()))));
+            }
+            "end" => {
+                let (name, scene) = match outer.take() {
+                    Some(o) => o,
+                    None => return Err(s.error("'end' without 'object'".to_string()))
+                };
+                let mut contents = mem::replace(&mut h, scene).into_vec();
+                if contents.iter().any(|o| o.axis_aligned_bounding_box().is_none()) {
+                    return Err(s.error(format!("object '{}' has unbounded contents", name)));
+                }
+                // A single object is kept as it is, so that it can still be sampled if it is a light
+                let object: Arc<dyn Hitable> = match contents.len() {
+                    1 => Arc::from(contents.remove(0)),
+                    _ => Arc::new(BvhNode::new(contents))
+                };
+                objects.insert(name, object);
+            }
+            "instance" => {
+                let name = s.name()?;
+                let object = match objects.get(name) {
+                    Some(o) => o.clone(),
+                    None => return Err(s.error(format!("unknown object '{}'", name)))
+                };
+                let transform = match parse_instance(&mut s, &materials)? {
+                    (start, Some(end), material) => Transform::moving(object, start, end, material),
+                    (m, None, material) => Transform::new(object, m, material)
+                };
+                match transform {
+                    Some(t) => h.push(Box::new(t)),
+                    None => return Err(s.error("'instance': transform flattens the object".to_string()))
+                }
+            }
+            "grid" => {
+                let name = s.name()?.to_string();
+                let grid = match s.name()? {
+                    "file" => {
+                        let file = dir.join(s.name()?);
+                        DensityGrid::load(&file).map_err(|e| s.error(format!("{}: {}", file.display(), e)))?
+                    }
+                    "noise" => {
+                        let field = parse_procedural(&mut s)?;
+                        let resolution = s.float()?;
+                        if !(1.0..=1024.0).contains(&resolution) {
+                            return Err(s.error("'grid': resolution must be from 1 to 1024".to_string()));
+                        }
+                        DensityGrid::from_noise(&field, resolution as usize)
+                    }
+                    other => return Err(s.error(format!("unknown grid type '{}'", other)))
+                };
+                grids.insert(name, Arc::new(grid));
+            }
+            "medium" => {
+                if outer.is_some() {
+                    return Err(s.error("'medium': media can not be part of objects".to_string()));
+                }
+                let name = s.name()?;
+                let boundary = match objects.get(name) {
+                    Some(o) => o.clone(),
+                    None => return Err(s.error(format!("unknown object '{}'", name)))
+                };
+                let density = s.float()?;
+                if density <= 0.0 {
+                    return Err(s.error("'medium': density must be positive".to_string()));
+                }
+                let m = lookup_material(&mut s, &materials)?;
+                media.push(Box::new(ConstantMedium::new(boundary, density, m)));
+            }
+            "volume" => {
+                if outer.is_some() {
+                    return Err(s.error("'volume': media can not be part of objects".to_string()));
+                }
+                let name = s.name()?;
+                let grid = match grids.get(name) {
+                    Some(g) => g.clone(),
+                    None => return Err(s.error(format!("unknown grid '{}'", name)))
+                };
+                let (p0, p1) = (s.vec3()?, s.vec3()?);
+                let density = s.float()?;
+                if density <= 0.0 {
+                    return Err(s.error("'volume': density must be positive".to_string()));
+                }
+                let m = lookup_material(&mut s, &materials)?;
+                let emission = match s.tokens.next() {
+                    Some("emit") => s.vec3()?,
+                    Some(other) => return Err(s.error(format!("'volume': unexpected '{}'", other))),
+                    None => Vec3::zero()
+                };
+                media.push(Box::new(GridMedium::new(p0, p1, grid, density, m, emission)));
+            }
+
+            _ => return Err(s.error(format!("unknown statement '{}'", s.keyword)))
+        }
+        s.end()?;
+    }
+
+    if let Some((name, _)) = outer {
+        return Err(LoadError::syntax(path, text.lines().count(), format!("object '{}' has no 'end'", name)));
+    }
+
+    let (lookfrom, lookat) = match (lookfrom, lookat) {
+        (Some(f), Some(a)) => (f, a),
+        _ => return Err(LoadError::syntax(path, text.lines().count(), "scene needs both 'lookfrom' and 'lookat'".to_string()))
+    };
+    let focus = focus.unwrap_or((lookfrom - lookat).length());
+
+    let cam = Camera::new(lookfrom, lookat, vup, vfov, aspect, aperture, focus).with_shutter(shutter.0, shutter.1);
+
+    let mut scene = Scene::new(h, cam);
+    scene.media = media;
+    if let Some(b) = background {
+        scene.background = b;
+    }
+
+    Ok(scene)
+}
@@ -0,0 +1,283 @@
+use std::f32;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use vec3::*;
+use ray::*;
+use material::*;
+use hitable::*;
+use aabb::*;
+
+// Flat shapes are given a little thickness in their bounding boxes, so that the slab test
+// does not divide a zero width
+const THICKNESS: f32 = 1e-4;
+
+// Infinite plane through a point. (u, v) are distances along two directions in the plane,
+// so textures repeat in world units.
+//
+pub struct Plane {
+    point: Vec3,
+    normal: Vec3,
+    tangents: (Vec3, Vec3),
+    material: Arc<dyn Material>
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, m: Arc<dyn Material>) -> Plane {
+        let normal = unit_vector(normal);
+        Plane { point, normal, tangents: orthonormal_basis(normal), material:m }
+    }
+}
+
+impl Hitable for Plane {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let denom = dot(r.direction(), self.normal);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = dot(self.point - r.origin(), self.normal) / denom;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        // Snapped onto the plane, so solid textures see it exactly where it is
+        let mut p = r.point_at_parameter(t);
+        p -= dot(p - self.point, self.normal) * self.normal;
+        let d = p - self.point;
+        Some(Hit { t, p, n: self.normal, ng: self.normal, u: dot(d, self.tangents.0), v: dot(d, self.tangents.1),
+                   uv_width: r.footprint(t), m: self.material.as_ref() })
+    }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+// Rectangle lying across two axes, at k along the third, facing along the third. (u, v) run
+// from 0 to 1 across it.
+//
+pub struct Rect {
+    axis: usize,            // Axis the rectangle faces along
+    a: (f32, f32),          // Extent along the first of the other two axes
+    b: (f32, f32),          // ... and along the second
+    k: f32,
+    material: Arc<dyn Material>
+}
+
+impl Rect {
+    pub fn new(axis: usize, a0: f32, a1: f32, b0: f32, b1: f32, k: f32, m: Arc<dyn Material>) -> Rect {
+        Rect { axis, a: (a0.min(a1), a0.max(a1)), b: (b0.min(b1), b0.max(b1)), k, material:m }
+    }
+
+    pub fn xy(x0: f32, x1: f32, y0: f32, y1: f32, k: f32, m: Arc<dyn Material>) -> Rect { Rect::new(2, x0, x1, y0, y1, k, m) }
+    pub fn xz(x0: f32, x1: f32, z0: f32, z1: f32, k: f32, m: Arc<dyn Material>) -> Rect { Rect::new(1, x0, x1, z0, z1, k, m) }
+    pub fn yz(y0: f32, y1: f32, z0: f32, z1: f32, k: f32, m: Arc<dyn Material>) -> Rect { Rect::new(0, y0, y1, z0, z1, k, m) }
+
+    // Indices of the two axes across the rectangle
+    fn across(&self) -> (usize, usize) {
+        match self.axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1)
+        }
+    }
+
+    fn point(&self, a: f32, b: f32) -> Vec3 {
+        let (ia, ib) = self.across();
+        let mut p = Vec3::zero();
+        p[ia] = a;
+        p[ib] = b;
+        p[self.axis] = self.k;
+        p
+    }
+
+    fn area(&self) -> f32 {
+        (self.a.1 - self.a.0) * (self.b.1 - self.b.0)
+    }
+}
+
+impl Hitable for Rect {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let (ia, ib) = self.across();
+        let t = (self.k - r.origin()[self.axis]) / r.direction()[self.axis];
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+
+        let p = r.point_at_parameter(t);
+        let (a, b) = (p[ia], p[ib]);
+        if a < self.a.0 || a > self.a.1 || b < self.b.0 || b > self.b.1 {
+            return None;
+        }
+
+        let mut n = Vec3::zero();
+        n[self.axis] = 1.0;
+        let (da, db) = (self.a.1 - self.a.0, self.b.1 - self.b.0);
+        let uv_width = r.footprint(t) / (da * db).sqrt();
+        Some(Hit { t, p: self.point(a, b), n, ng: n, u: (a - self.a.0) / da, v: (b - self.b.0) / db, uv_width, m: self.material.as_ref() })
+    }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        let mut lo = self.point(self.a.0, self.b.0);
+        let mut hi = self.point(self.a.1, self.b.1);
+        lo[self.axis] -= THICKNESS;
+        hi[self.axis] += THICKNESS;
+        Some(Aabb::new(lo, hi))
+    }
+
+    fn is_emissive(&self) -> bool { self.material.is_emissive() && self.area() > 0.0 }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f32 {
+        match self.hit(&Ray::new(o, v), 0.001, f32::MAX) {
+            Some(rec) => area_pdf(v, &rec, self.area()),
+            None => 0.0
+        }
+    }
+
+    fn random(&self, o: Vec3) -> Vec3 {
+        let a = self.a.0 + rand() * (self.a.1 - self.a.0);
+        let b = self.b.0 + rand() * (self.b.1 - self.b.0);
+        self.point(a, b) - o
+    }
+}
+
+// Disk facing along its normal. u is the angle around it, and v the distance out from the centre,
+// both from 0 to 1.
+//
+pub struct Disk {
+    centre: Vec3,
+    normal: Vec3,
+    radius: f32,
+    tangents: (Vec3, Vec3),
+    material: Arc<dyn Material>
+}
+
+impl Disk {
+    pub fn new(centre: Vec3, normal: Vec3, radius: f32, m: Arc<dyn Material>) -> Disk {
+        let normal = unit_vector(normal);
+        Disk { centre, normal, radius: radius.abs(), tangents: orthonormal_basis(normal), material:m }
+    }
+
+    fn area(&self) -> f32 {
+        PI * self.radius * self.radius
+    }
+}
+
+impl Hitable for Disk {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let denom = dot(r.direction(), self.normal);
+        if denom.abs() < 1e-12 {
+            return None;
+        }
+        let t = dot(self.centre - r.origin(), self.normal) / denom;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let p = r.point_at_parameter(t);
+        let d = p - self.centre;
+        let distance = d.length();
+        if distance > self.radius {
+            return None;
+        }
+
+        let phi = dot(d, self.tangents.1).atan2(dot(d, self.tangents.0));
+        let u = (phi / (2.0 * PI)).rem_euclid(1.0);
+        let uv_width = r.footprint(t) / (2.0 * PI * distance.max(0.01 * self.radius) * self.radius).sqrt();
+        Some(Hit { t, p, n: self.normal, ng: self.normal, u, v: distance / self.radius, uv_width, m: self.material.as_ref() })
+    }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        // A disk spreads along each axis by the radius times the sine of its normal's angle to it
+        let mut extent = Vec3::zero();
+        for i in 0..3 {
+            extent[i] = self.radius * (1.0 - self.normal[i] * self.normal[i]).max(0.0).sqrt() + THICKNESS;
+        }
+        Some(Aabb::new(self.centre - extent, self.centre + extent))
+    }
+
+    fn is_emissive(&self) -> bool { self.material.is_emissive() && self.area() > 0.0 }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f32 {
+        match self.hit(&Ray::new(o, v), 0.001, f32::MAX) {
+            Some(rec) => area_pdf(v, &rec, self.area()),
+            None => 0.0
+        }
+    }
+
+    fn random(&self, o: Vec3) -> Vec3 {
+        let r = self.radius * rand().sqrt();
+        let phi = 2.0 * PI * rand();
+        self.centre + r * phi.cos() * self.tangents.0 + r * phi.sin() * self.tangents.1 - o
+    }
+}
+
+// Axis aligned box, with outward normals. Each face has (u, v) from 0 to 1 across it.
+//
+pub struct Cuboid {
+    min: Vec3,
+    max: Vec3,
+    material: Arc<dyn Material>
+}
+
+impl Cuboid {
+    pub fn new(p0: Vec3, p1: Vec3, m: Arc<dyn Material>) -> Cuboid {
+        Cuboid { min: minimum(p0, p1), max: maximum(p0, p1), material:m }
+    }
+}
+
+impl Hitable for Cuboid {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        // Slab test, keeping track of which axis the entry and exit are on
+        let (mut t0, mut t1) = (-f32::MAX, f32::MAX);
+        let (mut axis0, mut axis1) = (0, 0);
+        for a in 0..3 {
+            let inv_d = 1.0 / r.direction()[a];
+            let mut ta = (self.min[a] - r.origin()[a]) * inv_d;
+            let mut tb = (self.max[a] - r.origin()[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut ta, &mut tb);
+            }
+            if ta > t0 {
+                t0 = ta;
+                axis0 = a;
+            }
+            if tb < t1 {
+                t1 = tb;
+                axis1 = a;
+            }
+        }
+        if t1 < t0 {
+            return None;
+        }
+
+        // The entry, unless it is out of range - from inside the box, that is the exit
+        let (t, axis) = if t0 > t_min && t0 < t_max {
+            (t0, axis0)
+        } else if t1 > t_min && t1 < t_max {
+            (t1, axis1)
+        } else {
+            return None;
+        };
+
+        let p = r.point_at_parameter(t);
+        let mut n = Vec3::zero();
+        n[axis] = if p[axis] - self.min[axis] < self.max[axis] - p[axis] { -1.0 } else { 1.0 };
+
+        let (ia, ib) = match axis {
+            0 => (1, 2),
+            1 => (0, 2),
+            _ => (0, 1)
+        };
+        let (da, db) = (self.max[ia] - self.min[ia], self.max[ib] - self.min[ib]);
+        let u = if da > 0.0 { (p[ia] - self.min[ia]) / da } else { 0.0 };
+        let v = if db > 0.0 { (p[ib] - self.min[ib]) / db } else { 0.0 };
+        let uv_width = if da * db > 0.0 { r.footprint(t) / (da * db).sqrt() } else { 0.0 };
+
+        Some(Hit { t, p, n, ng: n, u, v, uv_width, m: self.material.as_ref() })
+    }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+}
@@ -0,0 +1,183 @@
+use std::f32::consts::PI;
+
+use vec3::*;
+use background::*;
+
+// Daylight sky and sun
+//
+// The sky is the Preetham, Shirley and Smits analytic model ("A Practical Analytic Model for
+// Daylight", 1999): luminance and chromaticity at the zenith from the sun's height and the
+// turbidity (haziness - 2 is very clear, 10 is hazy), spread over the sky by the Perez
+// distribution. It is only defined above the horizon, so below it the horizon's colour is used.
+//
+// The sun is a disc of the real angular size, whose colour comes from Rayleigh and aerosol
+// extinction along the air mass it shines through.
+//
+// The world is y up, with north along -z and east along +x.
+//
+
+const SUN_ANGULAR_RADIUS: f32 = 0.004_65;      // Radians
+const SUN_LUMINANCE: f32 = 1.6e9;               // cd/m^2, outside the atmosphere
+
+// cd/m^2 to scene units - a white surface facing the noon sun comes out about 1
+const SCALE: f32 = 1.0 / 30_000.0;
+
+// Chance of sampling the sun disc rather than the whole sky, while the sun is up
+const SUN_SAMPLE_PROBABILITY: f32 = 0.5;
+
+// Perez coefficients A..E of a sky quantity, linear in turbidity
+fn perez_coefficients(t: f32, c: [[f32; 2]; 5]) -> [f32; 5] {
+    let mut p = [0.0; 5];
+    for i in 0..5 {
+        p[i] = c[i][0] * t + c[i][1];
+    }
+    p
+}
+
+fn perez(p: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+    (1.0 + p[0] * (p[1] / cos_theta).exp()) * (1.0 + p[2] * (p[3] * gamma).exp() + p[4] * gamma.cos() * gamma.cos())
+}
+
+// Zenith chromaticity, a cubic in the sun's zenith angle and quadratic in turbidity
+fn zenith_chromaticity(t: f32, theta_s: f32, m: [[f32; 4]; 3]) -> f32 {
+    let tv = [t * t, t, 1.0];
+    let sv = [theta_s * theta_s * theta_s, theta_s * theta_s, theta_s, 1.0];
+    (0..3).map(|i| tv[i] * (0..4).map(|j| m[i][j] * sv[j]).sum::<f32>()).sum()
+}
+
+fn yxy_to_rgb(y: f32, cx: f32, cy: f32) -> Vec3 {
+    let x = cx / cy * y;
+    let z = (1.0 - cx - cy) / cy * y;
+    Vec3::new( 3.2406 * x - 1.5372 * y - 0.4986 * z,
+              -0.9689 * x + 1.8758 * y + 0.0415 * z,
+               0.0557 * x - 0.2040 * y + 1.0570 * z)
+}
+
+// Sun direction from a place and time, following the appendix of the Preetham paper.
+// Latitude and longitude are in degrees (north and east positive), day is the day of the
+// year (1 - 365), and hour the local standard time, of the time zone whose meridian is
+// nearest the longitude.
+//
+pub fn sun_direction(latitude: f32, longitude: f32, day: f32, hour: f32) -> Vec3 {
+    let l = latitude.to_radians();
+    let meridian = (longitude / 15.0).round() * 15.0;
+
+    let solar_time = hour + 0.170 * (4.0 * PI * (day - 80.0) / 373.0).sin()
+                          - 0.129 * (2.0 * PI * (day - 8.0) / 355.0).sin()
+                          + (longitude - meridian) / 15.0;
+    let declination = 0.4093 * (2.0 * PI * (day - 81.0) / 368.0).sin();
+    let hour_angle = PI * solar_time / 12.0;
+
+    let elevation = (l.sin() * declination.sin() - l.cos() * declination.cos() * hour_angle.cos()).asin();
+    // Azimuth from south, towards west
+    let azimuth = (-declination.cos() * hour_angle.sin())
+        .atan2(l.cos() * declination.sin() - l.sin() * declination.cos() * hour_angle.cos());
+
+    Vec3::new(-elevation.cos() * azimuth.sin(), elevation.sin(), elevation.cos() * azimuth.cos())
+}
+
+pub struct Sky {
+    sun: Vec3,                  // Unit direction towards the sun
+    perez_y: [f32; 5],
+    perez_x: [f32; 5],
+    perez_yc: [f32; 5],
+    zenith: (f32, f32, f32),    // Luminance and chromaticity x, y, divided by Perez at the zenith
+    sun_radiance: Vec3,
+    sun_cos_max: f32,
+    sun_solid_angle: f32
+}
+
+impl Sky {
+    pub fn new(sun: Vec3, turbidity: f32) -> Sky {
+        let sun = unit_vector(sun);
+        let t = turbidity;
+        // Nothing is defined for a sun below the horizon - treat it as setting
+        let theta_s = sun.y().clamp(0.0, 1.0).acos().min(PI / 2.0 - 1e-3);
+
+        let perez_y = perez_coefficients(t, [[0.1787, -1.4630], [-0.3554, 0.4275], [-0.0227, 5.3251], [0.1206, -2.5771], [-0.0670, 0.3703]]);
+        let perez_x = perez_coefficients(t, [[-0.0193, -0.2592], [-0.0665, 0.0008], [-0.0004, 0.2125], [-0.0641, -0.8989], [-0.0033, 0.0452]]);
+        let perez_yc = perez_coefficients(t, [[-0.0167, -0.2608], [-0.0950, 0.0092], [-0.0079, 0.2102], [-0.0441, -1.6537], [-0.0109, 0.0529]]);
+
+        // Zenith luminance - the fit is in kcd/m^2
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = 1000.0 * ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192);
+        let zenith_x = zenith_chromaticity(t, theta_s, [[0.00166, -0.00375, 0.00209, 0.0],
+                                                        [-0.02903, 0.06377, -0.03202, 0.00394],
+                                                        [0.11693, -0.21196, 0.06052, 0.25886]]);
+        let zenith_yc = zenith_chromaticity(t, theta_s, [[0.00275, -0.00610, 0.00317, 0.0],
+                                                         [-0.04214, 0.08970, -0.04153, 0.00516],
+                                                         [0.15346, -0.26756, 0.06670, 0.26688]]);
+
+        // Air mass (Kasten), then extinction at the middle of each of R, G and B
+        let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+        let beta = 0.04608 * t - 0.04586;
+        let transmittance = |lambda: f32| (-air_mass * (0.008_735 * lambda.powf(-4.08) + beta * lambda.powf(-1.3))).exp();
+        let sun_radiance = if sun.y() > 0.0 {
+            SUN_LUMINANCE * SCALE * Vec3::new(transmittance(0.65), transmittance(0.55), transmittance(0.45))
+        } else {
+            Vec3::zero()
+        };
+
+        let half = SUN_ANGULAR_RADIUS / 2.0;
+        Sky {
+            sun,
+            zenith: (zenith_y / perez(&perez_y, 1.0, theta_s),
+                     zenith_x / perez(&perez_x, 1.0, theta_s),
+                     zenith_yc / perez(&perez_yc, 1.0, theta_s)),
+            perez_y, perez_x, perez_yc,
+            sun_radiance,
+            sun_cos_max: SUN_ANGULAR_RADIUS.cos(),
+            // 2 pi (1 - cos a), without the cancellation
+            sun_solid_angle: 4.0 * PI * half.sin() * half.sin()
+        }
+    }
+
+    fn sun_probability(&self) -> f32 {
+        if self.sun.y() > 0.0 { SUN_SAMPLE_PROBABILITY } else { 0.0 }
+    }
+
+    fn sky(&self, d: Vec3) -> Vec3 {
+        let cos_theta = d.y().max(1e-3);
+        let gamma = dot(d, self.sun).clamp(-1.0, 1.0).acos();
+
+        let y = self.zenith.0 * perez(&self.perez_y, cos_theta, gamma);
+        let cx = self.zenith.1 * perez(&self.perez_x, cos_theta, gamma);
+        let cy = self.zenith.2 * perez(&self.perez_yc, cos_theta, gamma);
+        let c = SCALE * yxy_to_rgb(y, cx, cy);
+        Vec3::new(c.r().max(0.0), c.g().max(0.0), c.b().max(0.0))
+    }
+}
+
+impl Background for Sky {
+    fn value(&self, d: Vec3) -> Vec3 {
+        let d = unit_vector(d);
+        let mut c = self.sky(d);
+        if dot(d, self.sun) >= self.sun_cos_max {
+            c += self.sun_radiance;
+        }
+        c
+    }
+
+    // The sun's disc, or else anywhere - the sky itself is smooth enough for the
+    // material's sampling to find
+    fn is_sampled(&self) -> bool { true }
+
+    fn pdf_value(&self, d: Vec3) -> f32 {
+        let in_sun = dot(unit_vector(d), self.sun) >= self.sun_cos_max;
+        let sun_pdf = if in_sun { 1.0 / self.sun_solid_angle } else { 0.0 };
+        let p = self.sun_probability();
+        p * sun_pdf + (1.0 - p) / (4.0 * PI)
+    }
+
+    fn random(&self) -> Vec3 {
+        if rand() < self.sun_probability() {
+            let cos_theta = 1.0 - rand() * self.sun_solid_angle / (2.0 * PI);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * rand();
+            let (u, v) = orthonormal_basis(self.sun);
+            sin_theta * phi.cos() * u + sin_theta * phi.sin() * v + cos_theta * self.sun
+        } else {
+            random_unit_vector()
+        }
+    }
+}
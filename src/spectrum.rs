@@ -0,0 +1,167 @@
+use std::sync::OnceLock;
+
+use vec3::*;
+
+// Wavelengths of light, and what colour they are
+//
+// The CIE 1931 colour matching functions are the multi-lobe Gaussian fit of Wyman, Sloan and
+// Shirley ("Simple Analytic Approximations to the CIE XYZ Color Matching Functions", 2013).
+//
+
+pub const LAMBDA_MIN: f32 = 380.0;
+pub const LAMBDA_MAX: f32 = 780.0;
+
+// Sodium D line, where glass's index of refraction is usually quoted
+pub const LAMBDA_D: f32 = 587.6;
+
+fn lobe(lambda: f32, mu: f32, sigma_below: f32, sigma_above: f32) -> f32 {
+    let t = (lambda - mu) / if lambda < mu { sigma_below } else { sigma_above };
+    (-0.5 * t * t).exp()
+}
+
+// CIE XYZ response to unit power at a wavelength in nanometres
+pub fn wavelength_to_xyz(lambda: f32) -> Vec3 {
+    Vec3::new(1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7) - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+              0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+              1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8))
+}
+
+// CIE XYZ to linear sRGB (D65 white)
+pub fn xyz_to_rgb(c: Vec3) -> Vec3 {
+    Vec3::new( 3.2406 * c.x() - 1.5372 * c.y() - 0.4986 * c.z(),
+              -0.9689 * c.x() + 1.8758 * c.y() + 0.0415 * c.z(),
+               0.0557 * c.x() - 0.2040 * c.y() + 1.0570 * c.z())
+}
+
+// sRGB colour of a wavelength, leaving out the colours sRGB can not show
+fn wavelength_to_rgb(lambda: f32) -> Vec3 {
+    let c = xyz_to_rgb(wavelength_to_xyz(lambda));
+    Vec3::new(c.r().max(0.0), c.g().max(0.0), c.b().max(0.0))
+}
+
+// wavelength_to_rgb() summed over the visible wavelengths, a nanometre at a time
+fn rgb_total() -> Vec3 {
+    static TOTAL: OnceLock<Vec3> = OnceLock::new();
+    *TOTAL.get_or_init(|| {
+        let mut total = Vec3::zero();
+        let mut lambda = LAMBDA_MIN + 0.5;
+        while lambda < LAMBDA_MAX {
+            total += wavelength_to_rgb(lambda);
+            lambda += 1.0;
+        }
+        total
+    })
+}
+
+// A wavelength picked uniformly, and the colour to weight light of that wavelength by - which
+// averages to white over many picks, so a path that splits white light into one wavelength
+// still comes out white where nothing depends on which one
+pub fn sample_wavelength() -> (f32, Vec3) {
+    let lambda = LAMBDA_MIN + rand() * (LAMBDA_MAX - LAMBDA_MIN);
+    (lambda, (LAMBDA_MAX - LAMBDA_MIN) * wavelength_to_rgb(lambda) / rgb_total())
+}
+
+// Spectral rendering
+//
+// Each path carries three wavelengths a third of the visible range apart, hero wavelength
+// sampling (Wilkie et al. 2014), with Vec3 holding a value at each. Colours given in RGB are
+// upsampled to smooth spectra that come back to the same RGB, and light reaching the camera is
+// turned into XYZ and then sRGB, white balanced so that a flat spectrum is white.
+//
+
+// A hero wavelength, picked uniformly over the visible range
+pub fn sample_hero_wavelength() -> f32 {
+    LAMBDA_MIN + rand() * (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+// The hero and the two wavelengths that go with it, wrapping around the visible range
+pub fn hero_wavelengths(hero: f32) -> [f32; 3] {
+    let range = LAMBDA_MAX - LAMBDA_MIN;
+    let at = |i: f32| LAMBDA_MIN + (hero - LAMBDA_MIN + i * range / 3.0) % range;
+    [hero, at(1.0), at(2.0)]
+}
+
+// sRGB of a flat spectrum of 1, before white balancing
+fn white() -> Vec3 {
+    static WHITE: OnceLock<Vec3> = OnceLock::new();
+    *WHITE.get_or_init(|| {
+        let mut total = Vec3::zero();
+        let mut lambda = LAMBDA_MIN + 0.5;
+        while lambda < LAMBDA_MAX {
+            total += xyz_to_rgb(wavelength_to_xyz(lambda));
+            lambda += 1.0;
+        }
+        total
+    })
+}
+
+// sRGB of a whole spectrum, given as a function of wavelength
+pub fn integrate_rgb<F: Fn(f32) -> f32>(f: F) -> Vec3 {
+    let mut xyz = Vec3::zero();
+    let mut lambda = LAMBDA_MIN + 0.5;
+    while lambda < LAMBDA_MAX {
+        xyz += f(lambda) * wavelength_to_xyz(lambda);
+        lambda += 1.0;
+    }
+    xyz_to_rgb(xyz) / white()
+}
+
+// sRGB estimated from one path's values at its wavelengths
+pub fn spectrum_to_rgb(values: Vec3, lambdas: [f32; 3]) -> Vec3 {
+    let mut xyz = Vec3::zero();
+    for i in 0..3 {
+        xyz += values[i] * wavelength_to_xyz(lambdas[i]);
+    }
+    xyz_to_rgb(xyz) * ((LAMBDA_MAX - LAMBDA_MIN) / 3.0) / white()
+}
+
+// Smooth spectra for red, green and blue, which add up to a flat spectrum of 1
+fn basis(lambda: f32) -> Vec3 {
+    let bump = |mu: f32, sigma: f32| (-0.5 * ((lambda - mu) / sigma) * ((lambda - mu) / sigma)).exp();
+    let b = Vec3::new(bump(610.0, 40.0), bump(545.0, 35.0), bump(455.0, 30.0));
+    b / (b.r() + b.g() + b.b())
+}
+
+// Rows of the inverse of the matrix taking basis weights to sRGB
+fn rgb_to_basis() -> [Vec3; 3] {
+    static INVERSE: OnceLock<[Vec3; 3]> = OnceLock::new();
+    *INVERSE.get_or_init(|| {
+        let a = integrate_rgb(|l| basis(l).r());
+        let b = integrate_rgb(|l| basis(l).g());
+        let c = integrate_rgb(|l| basis(l).b());
+        let det = dot(a, cross(b, c));
+        [cross(b, c) / det, cross(c, a) / det, cross(a, b) / det]
+    })
+}
+
+// An RGB colour as a spectrum, at each of a path's wavelengths. White and greys are flat, and
+// the few saturated colours that would need a negative amount of light somewhere are clipped.
+pub fn rgb_to_spectrum(c: Vec3, lambdas: [f32; 3]) -> Vec3 {
+    let m = rgb_to_basis();
+    let w = Vec3::new(dot(m[0], c), dot(m[1], c), dot(m[2], c));
+    let at = |lambda: f32| dot(w, basis(lambda)).max(0.0);
+    Vec3::new(at(lambdas[0]), at(lambdas[1]), at(lambdas[2]))
+}
+
+// Planck's law - light given off at a wavelength in nanometres by a black body at a temperature
+// in kelvin, relative to that at the peak of the spectrum
+pub fn planck(lambda: f32, kelvin: f32) -> f32 {
+    // Second radiation constant, in nanometre kelvins
+    const C2: f64 = 1.438_777e7;
+    let radiance = |l: f64| 1.0 / (l.powi(5) * ((C2 / (l * kelvin as f64)).exp() - 1.0));
+    let peak = 2.897_772e6 / kelvin as f64;
+    (radiance(lambda as f64) / radiance(peak)) as f32
+}
+
+// Wavelengths to average something that depends on wavelength over, to find it in RGB, with how
+// much each counts towards red, green and blue - each channel's weights add up to one
+pub fn rgb_samples() -> &'static [(f32, Vec3)] {
+    const COUNT: usize = 16;
+    static SAMPLES: OnceLock<Vec<(f32, Vec3)>> = OnceLock::new();
+    SAMPLES.get_or_init(|| {
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / COUNT as f32;
+        let lambdas: Vec<f32> = (0..COUNT).map(|i| LAMBDA_MIN + (i as f32 + 0.5) * step).collect();
+        let total = lambdas.iter().fold(Vec3::zero(), |t, &l| t + wavelength_to_rgb(l));
+        lambdas.iter().map(|&l| (l, wavelength_to_rgb(l) / total)).collect()
+    })
+}
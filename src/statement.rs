@@ -0,0 +1,79 @@
+use std::path::Path;
+use std::str::SplitWhitespace;
+
+use vec3::*;
+use error::*;
+
+// One statement of a line based text format (OBJ, MTL, scenes) - a keyword followed by
+// whitespace separated arguments, tagged with where it came from for error messages
+//
+pub struct Statement<'a> {
+    pub path: &'a Path,
+    pub line: usize,
+    pub keyword: &'a str,
+    pub tokens: SplitWhitespace<'a>
+}
+
+impl<'a> Statement<'a> {
+    pub fn error(&self, message: String) -> LoadError {
+        LoadError::syntax(self.path, self.line, message)
+    }
+
+    pub fn next_float(&mut self) -> Result<Option<f32>, LoadError> {
+        match self.tokens.next() {
+            Some(t) => t.parse::<f32>().map(Some).map_err(|_| self.error(format!("'{}': bad number '{}'", self.keyword, t))),
+            None => Ok(None)
+        }
+    }
+
+    pub fn float(&mut self) -> Result<f32, LoadError> {
+        match self.next_float()? {
+            Some(f) => Ok(f),
+            None => Err(self.error(format!("'{}': too few values", self.keyword)))
+        }
+    }
+
+    pub fn integer(&mut self) -> Result<i32, LoadError> {
+        match self.tokens.next() {
+            Some(t) => t.parse::<i32>().map_err(|_| self.error(format!("'{}': bad whole number '{}'", self.keyword, t))),
+            None => Err(self.error(format!("'{}': too few values", self.keyword)))
+        }
+    }
+
+    pub fn vec3(&mut self) -> Result<Vec3, LoadError> {
+        Ok(Vec3::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    pub fn name(&mut self) -> Result<&'a str, LoadError> {
+        match self.tokens.next() {
+            Some(t) => Ok(t),
+            None => Err(self.error(format!("'{}': missing name", self.keyword)))
+        }
+    }
+
+    // The next token, without consuming it
+    pub fn peek(&self) -> Option<&'a str> {
+        self.tokens.clone().next()
+    }
+
+    // Trailing values are optional in some statements, but anything more is a mistake
+    pub fn end(&mut self) -> Result<(), LoadError> {
+        match self.tokens.next() {
+            Some(t) => Err(self.error(format!("'{}': unexpected '{}'", self.keyword, t))),
+            None => Ok(())
+        }
+    }
+}
+
+// Split a file into statements, dropping comments and blank lines
+//
+pub fn statements<'a>(path: &'a Path, text: &'a str) -> impl Iterator<Item=Statement<'a>> {
+    text.lines().enumerate().filter_map(move |(n, l)| {
+        let l = match l.find('#') {
+            Some(c) => &l[..c],
+            None => l
+        };
+        let mut tokens = l.split_whitespace();
+        tokens.next().map(|keyword| Statement { path, line: n + 1, keyword, tokens })
+    })
+}
@@ -0,0 +1,222 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use image;
+
+use vec3::*;
+use noise::*;
+
+// Textures give a colour for a surface point, from its (u, v) coordinates or its position
+//
+// Width is the size of the area around (u, v) the ray covers, for textures that filter
+//
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f32, v: f32, p: Vec3, width: f32) -> Vec3;
+}
+
+// Solid colour
+//
+pub struct SolidColour {
+    colour: Vec3
+}
+
+impl SolidColour {
+    pub fn new(c: Vec3) -> SolidColour { SolidColour { colour:c } }
+}
+
+impl Texture for SolidColour {
+    fn value(&self, _u: f32, _v: f32, _p: Vec3, _width: f32) -> Vec3 {
+        self.colour
+    }
+}
+
+// 3D checkerboard - cubes of the given size, alternating between two textures
+//
+pub struct Checker {
+    odd: Arc<dyn Texture>,
+    even: Arc<dyn Texture>,
+    size: f32
+}
+
+impl Checker {
+    pub fn new(odd: Arc<dyn Texture>, even: Arc<dyn Texture>, size: f32) -> Checker { Checker { odd, even, size } }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f32, v: f32, p: Vec3, width: f32) -> Vec3 {
+        let cell = |x: f32| (x / self.size).floor() as i64;
+        if (cell(p.x()) + cell(p.y()) + cell(p.z())) & 1 == 1 {
+            self.odd.value(u, v, p, width)
+        } else {
+            self.even.value(u, v, p, width)
+        }
+    }
+}
+
+// How an image texture is sampled
+//
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Filter {
+    Bilinear,       // Blend of the four nearest texels
+    Trilinear       // Bilinear from the two mipmap levels nearest the ray's footprint, blended
+}
+
+// What lies outside (u, v) in [0, 1]
+//
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror
+}
+
+impl Wrap {
+    fn apply(self, i: i64, n: usize) -> usize {
+        let n = n as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(n),
+            Wrap::Clamp => i.clamp(0, n - 1),
+            Wrap::Mirror => {
+                let m = i.rem_euclid(2 * n);
+                if m < n { m } else { 2 * n - 1 - m }
+            }
+        };
+        i as usize
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+// Read an image as linear RGB, top row first. Floating point images (HDR, EXR) are taken
+// as linear already, and anything else as sRGB.
+//
+pub fn load_linear(path: &Path) -> image::ImageResult<(usize, usize, Vec<Vec3>)> {
+    let img = image::open(path)?;
+    let linear = matches!(img, image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_));
+    let img = img.to_rgb32f();
+
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let pixels = img.pixels().map(|p| {
+        if linear {
+            Vec3::new(p[0], p[1], p[2])
+        } else {
+            Vec3::new(srgb_to_linear(p[0]), srgb_to_linear(p[1]), srgb_to_linear(p[2]))
+        }
+    }).collect();
+    Ok((width, height, pixels))
+}
+
+struct MipLevel {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>       // Top row first
+}
+
+impl MipLevel {
+    fn texel(&self, i: i64, j: i64, wrap: Wrap) -> Vec3 {
+        self.pixels[wrap.apply(j, self.height) * self.width + wrap.apply(i, self.width)]
+    }
+
+    fn bilinear(&self, u: f32, v: f32, wrap: Wrap) -> Vec3 {
+        // Texel centres are at half integers
+        let x = u * self.width as f32 - 0.5;
+        let y = (1.0 - v) * self.height as f32 - 0.5;
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+        let (i, j) = (x.floor() as i64, y.floor() as i64);
+
+        let top = (1.0 - fx) * self.texel(i, j, wrap) + fx * self.texel(i + 1, j, wrap);
+        let bottom = (1.0 - fx) * self.texel(i, j + 1, wrap) + fx * self.texel(i + 1, j + 1, wrap);
+        (1.0 - fy) * top + fy * bottom
+    }
+
+    // Half the size, each texel the average of the (up to) four it covers
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let (y0, y1) = ((2 * y).min(self.height - 1), (2 * y + 1).min(self.height - 1));
+            for x in 0..width {
+                let (x0, x1) = ((2 * x).min(self.width - 1), (2 * x + 1).min(self.width - 1));
+                pixels.push(0.25 * (self.pixels[y0 * self.width + x0] + self.pixels[y0 * self.width + x1] +
+                                    self.pixels[y1 * self.width + x0] + self.pixels[y1 * self.width + x1]));
+            }
+        }
+        MipLevel { width, height, pixels }
+    }
+}
+
+// Image mapped over (u, v) in [0, 1], v = 0 at the bottom
+//
+// Pixels are linear. Each level of the mipmap is half the size of the one before, down to
+// a single texel, so that trilinear filtering can pick the level whose texels are about
+// the size of the ray's footprint.
+//
+pub struct ImageTexture {
+    levels: Vec<MipLevel>,
+    filter: Filter,
+    wrap: Wrap
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Vec3>, filter: Filter, wrap: Wrap) -> ImageTexture {
+        assert!(width > 0 && height > 0, "empty image texture");
+        assert_eq!(pixels.len(), width * height, "image texture size mismatch");
+
+        let mut levels = vec![MipLevel { width, height, pixels }];
+        if filter == Filter::Trilinear {
+            while levels.last().is_some_and(|l| l.width > 1 || l.height > 1) {
+                let next = levels[levels.len() - 1].downsample();
+                levels.push(next);
+            }
+        }
+        ImageTexture { levels, filter, wrap }
+    }
+
+    pub fn load(path: &Path, filter: Filter, wrap: Wrap) -> image::ImageResult<ImageTexture> {
+        let (width, height, pixels) = load_linear(path)?;
+        Ok(ImageTexture::new(width, height, pixels, filter, wrap))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _p: Vec3, width: f32) -> Vec3 {
+        let base = &self.levels[0];
+        if self.filter == Filter::Bilinear {
+            return base.bilinear(u, v, self.wrap);
+        }
+
+        // Level whose texels are the width of the footprint
+        let top = (self.levels.len() - 1) as f32;
+        let lod = (width * base.width.max(base.height) as f32).log2().clamp(0.0, top);
+        let l = lod.floor() as usize;
+        let f = lod - l as f32;
+
+        let fine = self.levels[l].bilinear(u, v, self.wrap);
+        if f <= 0.0 {
+            return fine;
+        }
+        (1.0 - f) * fine + f * self.levels[l + 1].bilinear(u, v, self.wrap)
+    }
+}
+
+// Procedural pattern, blending from one texture where it is 0 to another where it is 1
+//
+pub struct NoiseTexture {
+    field: Procedural,
+    low: Arc<dyn Texture>,
+    high: Arc<dyn Texture>
+}
+
+impl NoiseTexture {
+    pub fn new(field: Procedural, low: Arc<dyn Texture>, high: Arc<dyn Texture>) -> NoiseTexture { NoiseTexture { field, low, high } }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, u: f32, v: f32, p: Vec3, width: f32) -> Vec3 {
+        let t = self.field.value(p);
+        (1.0 - t) * self.low.value(u, v, p, width) + t * self.high.value(u, v, p, width)
+    }
+}
@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use vec3::*;
+use ray::*;
+use material::*;
+use hitable::*;
+use aabb::*;
+use matrix::*;
+
+// An object placed in the world by an affine transform, optionally with its material replaced
+//
+// Rays are taken into the object's space rather than the object being moved, so any number of
+// Transforms can share one object - a heavy mesh is only held once however often it appears.
+// The ray direction is not renormalised, so t is the same in both spaces.
+//
+// A moving transform goes from one matrix at time 0 to another at time 1, each split into a
+// translation, rotation and scale: the translation and scale blend, and the rotation turns at
+// a steady rate, so a spinning object keeps its shape all through the shutter interval.
+//
+
+struct Placement {
+    to_world: Mat4,
+    to_object: Mat4,
+    normal_to_world: Mat4       // Inverse transpose
+}
+
+impl Placement {
+    // None if the transform flattens the object
+    fn new(to_world: Mat4) -> Option<Placement> {
+        let to_object = to_world.inverse()?;
+        Some(Placement { to_world, to_object, normal_to_world: to_object.transpose() })
+    }
+}
+
+// The two ends of a moving transform, split up once rather than for every ray
+struct Motion {
+    start: Decomposed,
+    end: Decomposed
+}
+
+// Steps through the shutter interval at which a moving transform's bounds are taken
+const BOUND_STEPS: usize = 16;
+
+pub struct Transform {
+    object: Arc<dyn Hitable>,
+    start: Placement,
+    motion: Option<Motion>,     // How the object moves to where it is at time 1, if it does
+    material: Option<Arc<dyn Material>>
+}
+
+fn corners(b: &Aabb) -> [Vec3; 8] {
+    let mut c = [Vec3::zero(); 8];
+    for (i, corner) in c.iter_mut().enumerate() {
+        *corner = Vec3::new(if i & 1 == 0 { b.min().x() } else { b.max().x() },
+                            if i & 2 == 0 { b.min().y() } else { b.max().y() },
+                            if i & 4 == 0 { b.min().z() } else { b.max().z() });
+    }
+    c
+}
+
+impl Transform {
+    // None if the transform flattens the object
+    pub fn new(object: Arc<dyn Hitable>, to_world: Mat4, material: Option<Arc<dyn Material>>) -> Option<Transform> {
+        Some(Transform { object, start: Placement::new(to_world)?, motion: None, material })
+    }
+
+    // None if the transform flattens the object at either end
+    pub fn moving(object: Arc<dyn Hitable>, start: Mat4, end: Mat4, material: Option<Arc<dyn Material>>) -> Option<Transform> {
+        let motion = Motion { start: start.decompose()?, end: end.decompose()? };
+        Some(Transform { object, start: Placement::new(start)?, motion: Some(motion), material })
+    }
+
+    fn bounding_box_at(&self, to_world: &Mat4) -> Option<Aabb> {
+        let b = self.object.axis_aligned_bounding_box()?;
+        let mut bbox = Aabb::empty();
+        for corner in corners(&b).iter() {
+            let p = to_world.transform_point(*corner);
+            bbox = surrounding_box(bbox, Aabb::new(p, p));
+        }
+        Some(bbox)
+    }
+
+    // Corners move along arcs as the object turns, so the box is made of the boxes at steps
+    // through the shutter interval, each grown by how far a corner can get from its nearest step
+    fn moving_bounding_box(&self, motion: &Motion) -> Option<Aabb> {
+        let b = self.object.axis_aligned_bounding_box()?;
+        let (start, end) = (&motion.start, &motion.end);
+
+        let mut reach: f32 = 0.0;
+        let mut scaling: f32 = 0.0;
+        for corner in corners(&b).iter() {
+            let (c0, c1) = (start.scale.transform_vector(*corner), end.scale.transform_vector(*corner));
+            reach = reach.max(c0.length()).max(c1.length());
+            scaling = scaling.max((c1 - c0).length());
+        }
+        let travel = (end.translation - start.translation).length();
+        let turn = Quaternion::angle_between(&start.rotation, &end.rotation);
+        let grow = 0.5 * (travel + scaling + reach * turn) / BOUND_STEPS as f32;
+
+        let mut bbox = Aabb::empty();
+        for i in 0..=BOUND_STEPS {
+            let at = Decomposed::interpolate(start, end, i as f32 / BOUND_STEPS as f32).matrix();
+            bbox = surrounding_box(bbox, self.bounding_box_at(&at)?);
+        }
+        let grow = Vec3::new(grow, grow, grow);
+        Some(Aabb::new(bbox.min() - grow, bbox.max() + grow))
+    }
+}
+
+impl Hitable for Transform {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        let between;
+        let placement = match self.motion {
+            Some(ref motion) => {
+                // A scale passing through zero part way flattens the object there
+                between = Placement::new(Decomposed::interpolate(&motion.start, &motion.end, r.time()).matrix())?;
+                &between
+            }
+            None => &self.start
+        };
+
+        let local = r.with_line(placement.to_object.transform_point(r.origin()), placement.to_object.transform_vector(r.direction()));
+        let mut rec = self.object.hit(&local, t_min, t_max)?;
+
+        rec.p = placement.to_world.transform_point(rec.p);
+        rec.n = unit_vector(placement.normal_to_world.transform_vector(rec.n));
+        rec.ng = unit_vector(placement.normal_to_world.transform_vector(rec.ng));
+        if let Some(ref m) = self.material {
+            rec.m = m.as_ref();
+        }
+        Some(rec)
+    }
+
+    fn axis_aligned_bounding_box(&self) -> Option<Aabb> {
+        match self.motion {
+            Some(ref motion) => self.moving_bounding_box(motion),
+            None => self.bounding_box_at(&self.start.to_world)
+        }
+    }
+
+    // A replacement material can not make an object that can not be sampled into a light, and
+    // sampling does not know the time, so moving lights are not sampled either - they still
+    // glow when hit
+    fn is_emissive(&self) -> bool {
+        self.motion.is_none() && self.object.is_emissive() && self.material.as_ref().is_none_or(|m| m.is_emissive())
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3) -> f32 {
+        let to_object = &self.start.to_object;
+        let p = self.object.pdf_value(to_object.transform_point(o), to_object.transform_vector(v));
+        if p <= 0.0 {
+            return 0.0;
+        }
+
+        // Solid angle around a direction is stretched by det / |M v|^3 going into object space
+        let length = to_object.transform_vector(unit_vector(v)).length();
+        p * to_object.determinant3().abs() / (length * length * length)
+    }
+
+    fn random(&self, o: Vec3) -> Vec3 {
+        self.start.to_world.transform_vector(self.object.random(self.start.to_object.transform_point(o)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Distance to a unit sphere spun half a turn, and carried along x, at mid shutter
+    #[test]
+    fn moving_instance_keeps_its_size() {
+        let sphere: Arc<dyn Hitable> = Arc::new(Sphere::new(Vec3::zero(), 1.0, Arc::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))));
+        let end = Mat4::translate(Vec3::new(2.0, 0.0, 0.0)) * Mat4::rotate(180.0, Vec3::new(0.0, 1.0, 0.0));
+        let t = Transform::moving(sphere, Mat4::identity(), end, None).unwrap();
+
+        // Centred at (1, 0, 0) half way through
+        for &(x, y, expected) in [(1.0, 0.0, 4.0), (1.0, 0.8, 4.4), (1.8, 0.0, 4.4), (0.2, 0.0, 4.4)].iter() {
+            let r = Ray::new(Vec3::new(x, y, -5.0), Vec3::new(0.0, 0.0, 1.0)).with_time(0.5);
+            let rec = t.hit(&r, 0.001, f32::MAX).expect("ray should hit the sphere");
+            assert!((rec.t - expected).abs() < 1e-3, "hit at {} rather than {}", rec.t, expected);
+        }
+
+        // ... and no bigger
+        let r = Ray::new(Vec3::new(2.05, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0)).with_time(0.5);
+        assert!(t.hit(&r, 0.001, f32::MAX).is_none());
+
+        let bbox = t.axis_aligned_bounding_box().unwrap();
+        assert!(bbox.min().x() <= 0.0 && bbox.max().x() >= 2.0);
+    }
+}
@@ -2,7 +2,8 @@ extern crate rand;
 
 use std::fmt;
 use std::ops::*;
-use rand::Rng;
+use std::cell::RefCell;
+use rand::{Rng, SeedableRng, XorShiftRng};
 
 #[derive(Copy, Clone, Debug)]
 pub struct Vec3 {
@@ -130,10 +131,26 @@ pub fn cross(v1: Vec3, v2: Vec3) -> Vec3 {
               v1.e[0] * v2.e[1] - v1.e[1] * v2.e[0]] }
 }
 
+pub fn minimum(v1: Vec3, v2: Vec3) -> Vec3 {
+    Vec3 { e:[v1.e[0].min(v2.e[0]), v1.e[1].min(v2.e[1]), v1.e[2].min(v2.e[2])] }
+}
+
+pub fn maximum(v1: Vec3, v2: Vec3) -> Vec3 {
+    Vec3 { e:[v1.e[0].max(v2.e[0]), v1.e[1].max(v2.e[1]), v1.e[2].max(v2.e[2])] }
+}
 
 pub fn unit_vector(v: Vec3) -> Vec3  { v * (1.0 / v.length()) }
 
-pub fn reflect(v: Vec3, n:Vec3) -> Vec3 { v - 2.8 * dot(v,n) * n }
+// Two unit vectors that, with n, make an orthonormal basis (Duff et al. 2017)
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f32.copysign(n.z());
+    let a = -1.0 / (sign + n.z());
+    let b = n.x() * n.y() * a;
+    (Vec3::new(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x()),
+     Vec3::new(b, sign + n.y() * n.y() * a, -n.y()))
+}
+
+pub fn reflect(v: Vec3, n:Vec3) -> Vec3 { v - 2.0 * dot(v,n) * n }
 
 pub fn refract(v: Vec3, n:Vec3, ni_over_nt:f32) -> Option<Vec3> {
     let uv = unit_vector(v);
@@ -146,8 +163,36 @@ pub fn refract(v: Vec3, n:Vec3, ni_over_nt:f32) -> Option<Vec3> {
     }
 }
 
+// Each thread has its own generator, so rendering threads never contend for it.
+// Reseeding per unit of work makes the output independent of thread scheduling.
+//
+thread_local! {
+    static RNG: RefCell<XorShiftRng> = RefCell::new(XorShiftRng::new_unseeded());
+}
+
+// Step of the splitmix64 generator - every output bit depends on every bit of the state
+pub fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut x = *state;
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+pub fn seed_rand(seed: u64) {
+    // Spread the seed over the whole generator state with splitmix64 - xorshift must not be all zero
+    let mut z = seed;
+    let (a, b) = (splitmix64(&mut z), splitmix64(&mut z));
+    let state = [a as u32, (a >> 32) as u32 | 1, b as u32, (b >> 32) as u32];
+    RNG.with(|rng| *rng.borrow_mut() = XorShiftRng::from_seed(state));
+}
+
 pub fn rand() -> f32 {
-    rand::thread_rng().gen_range(0.0, 1.0)
+    RNG.with(|rng| rng.borrow_mut().next_f32())
+}
+
+pub fn rand_u64() -> u64 {
+    RNG.with(|rng| rng.borrow_mut().next_u64())
 }
 
 pub fn random_in_unit_sphere() -> Vec3 {
@@ -159,6 +204,10 @@ pub fn random_in_unit_sphere() -> Vec3 {
     }
 }
 
+pub fn random_unit_vector() -> Vec3 {
+    unit_vector(random_in_unit_sphere())
+}
+
 pub fn random_in_unit_disk() -> Vec3 {
     loop {
         let p = (2.0 * Vec3::new(rand(), rand(), 0.0)) - Vec3::new(1.0,1.0,0.0);